@@ -5,14 +5,14 @@ fn main() -> ExitCode {
 	let cli = match Cli::dotsetup().parse_args() {
 		Ok(cli) => cli,
 		Err(e) => {
-			error!("{e}");
-			return ExitCode::FAILURE;
+			error!("{}", e.report());
+			return ExitCode::from(e.exit_code());
 		}
 	};
 
 	if let Err(e) = cli.run() {
-		error!("{e}");
-		ExitCode::FAILURE
+		error!("{}", e.report());
+		ExitCode::from(e.exit_code())
 	} else {
 		ExitCode::SUCCESS
 	}
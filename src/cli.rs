@@ -1,11 +1,28 @@
 pub mod action;
+pub mod args;
+pub mod completions;
 pub mod config;
+pub mod edit;
+pub mod init;
+pub mod output;
 
 pub use action::*;
+use args::{Found, OPTIONS, Scope};
 pub use config::*;
+pub use output::Output;
 
-use crate::{VERSION, arg_error, error::Result, sys_error};
-use std::{env, path::PathBuf};
+use crate::{
+	VERSION, arg_error, config_error,
+	error::{Error, Result},
+	info,
+	log::{self, Level},
+	sys_error, trace,
+};
+use std::{
+	env,
+	fs::{self, OpenOptions},
+	path::{Path, PathBuf},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Name {
@@ -24,12 +41,33 @@ impl std::fmt::Display for Name {
 }
 
 /// The main command-line interface (dotbackup & dotsetup).
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Clone, Default)]
 pub struct Cli {
 	/// dotbackup or dotsetup
 	pub name: Name,
 	pub config: Config,
 	pub action: Action,
+	/// output format of `--dump-config`
+	pub format: Format,
+	/// answer yes to all questions
+	pub yes: bool,
+	/// back up files right after `add`
+	pub backup_now: bool,
+	/// recipe of the app for `add`
+	pub recipe: Option<String>,
+	/// show changes of setup instead of backup for `diff`
+	pub setup: bool,
+	/// output format of list, status, diff, backup and setup
+	pub output: Output,
+	/// command given on the command line, `None` for the default
+	pub command: Option<&'static args::Command>,
+	/// log level given by `-q` or `-v`, `None` to keep the current level
+	pub log_level: Option<Level>,
+	/// file to mirror log messages to
+	pub log_file: Option<PathBuf>,
+	/// do not show progress of copying files
+	pub no_progress: bool,
 }
 
 impl Cli {
@@ -73,106 +111,353 @@ impl Cli {
 			.join("dotbackup"))
 	}
 
+	/// Return the path of configuration `name` in `dir`, whose extension is
+	/// one of the supported formats. Fall back to YAML if no file exists.
+	#[must_use]
+	pub fn find_config(dir: &Path, name: &str) -> PathBuf {
+		Format::ALL
+			.iter()
+			.map(|format| dir.join(format!("{name}.{}", format.extension())))
+			.find(|path| path.is_file())
+			.unwrap_or(dir.join(format!("{name}.yml")))
+	}
+
 	pub fn default_config_path() -> Result<PathBuf> {
-		let path = PathBuf::from("dotbackup.yml");
+		let path = Self::find_config(Path::new(""), "dotbackup");
 
 		Ok(if path.is_file() {
 			path
 		} else {
-			Self::config_dir()?.join(path)
+			Self::find_config(&Self::config_dir()?, "dotbackup")
 		})
 	}
 
+	/// Apply `log_level` and `log_file` to the logger.
+	pub fn init_logger(&self) -> Result<()> {
+		if let Some(level) = self.log_level {
+			log::set_level(level);
+		}
+		if let Some(path) = &self.log_file {
+			let file = OpenOptions::new()
+				.create(true)
+				.append(true)
+				.open(path)
+				.map_err(|e| Error::io("open log file", path, e))?;
+			log::set_log_file(Some(file));
+		}
+
+		Ok(())
+	}
+
 	/// Parse command-line arguments and also parse config.
-	pub fn parse_args(mut self) -> Result<Self> {
-		let mut is_config_provided = false;
-		let mut args = env::args();
-		args.next();
-
-		while let Some(arg) = args.next() {
-			match &arg[..] {
-				"-h" | "--help" => {
-					return Ok(self.action(Action::Help));
-				}
-				"-f" | "--file" => {
-					let file_path = args
-						.next()
-						.ok_or(arg_error!("expected a file path after option {arg}"))?;
-					self.config.apply_file(&PathBuf::from(file_path))?;
-					is_config_provided = true;
-				}
-				"-c" | "--config" => {
-					let config_name = args.next().ok_or(arg_error!(
-						"expected a configuration name after option {arg}"
-					))?;
-					self.config
-						.apply_file(&Self::config_dir()?.join(format!("{config_name}.yml")))?;
-					is_config_provided = true;
-				}
-				"-l" | "--list" => {
-					self.action = Action::List;
+	pub fn parse_args(self) -> Result<Self> {
+		self.parse_from(env::args().skip(1))
+	}
+
+	/// Parse `args` (without the program name) and also parse config.
+	///
+	/// The first positional argument of dotbackup is a command if it is one
+	/// of `COMMANDS` and it is not after `--`.
+	pub fn parse_from(mut self, args: impl IntoIterator<Item = String>) -> Result<Self> {
+		let (options, mut positionals, before) = args::split(args)?;
+		if self.name == Name::Dotbackup
+			&& before > 0
+			&& let Ok(command) = args::command(&positionals[0])
+		{
+			self.command = Some(command);
+			positionals.remove(0);
+		}
+		let command = self.command.map(|command| command.name);
+
+		let mut config_path = None;
+		for Found { opt, arg, value } in options {
+			if !opt.applies_to(command) {
+				return Err(arg_error!(
+					"option {arg} is not supported by command {}",
+					command.unwrap_or_default()
+				));
+			}
+			let value = value.unwrap_or_default();
+
+			match opt.long {
+				"help" => return Ok(self.action(Action::Help)),
+				"file" => config_path = Some(PathBuf::from(value)),
+				"config" => config_path = Some(Self::find_config(&Self::config_dir()?, &value)),
+				"exclude" => self.config.excluded_apps.push(value),
+				"list" => self.action = Action::List,
+				"clean" => self.config.clean = true,
+				"no-progress" => self.no_progress = true,
+				"strict-missing" => self.config.strict_missing = true,
+				"strict" => self.config.strict = true,
+				"jobs" => {
+					self.config.jobs = match value.parse() {
+						Ok(0) | Err(_) => {
+							return Err(arg_error!("expected a positive number, found {value:?}"));
+						}
+						Ok(jobs) => jobs,
+					};
 				}
-				"--clean" => self.config.clean = true,
-				"-V" | "--version" => return Ok(self.action(Action::Version)),
-				"-v" | "--verbose" => self.config.verbose = true,
-				"--dump-config" => self.action = Action::DumpConfig,
-				_ => {
-					if arg.starts_with('-') {
-						return Err(arg_error!("unknown argument: {arg:?}"));
-					}
-					self.config.selected_apps.push(arg);
+				"version" => return Ok(self.action(Action::Version)),
+				"verbose" => {
+					self.config.verbose = true;
+					self.log_level = Some(match self.log_level {
+						Some(Level::Verbose | Level::Trace) => Level::Trace,
+						_ => Level::Verbose,
+					});
 				}
+				"quiet" => self.log_level = Some(Level::Quiet),
+				"log-file" => self.log_file = Some(PathBuf::from(value)),
+				"dump-config" => self.action = Action::DumpConfig,
+				"format" => self.format = value.parse()?,
+				"output" => self.output = value.parse()?,
+				"check" => self.action = Action::Check,
+				"init" => self.action = Action::Init,
+				"yes" => self.yes = true,
+				"print-schema" => return Ok(self.action(Action::PrintSchema)),
+				"backup" => self.backup_now = true,
+				"recipe" => self.recipe = Some(value),
+				"setup" => self.setup = true,
+				"completions" => return Ok(self.action(Action::Completions(value.parse()?))),
+				"complete-apps" => self.action = Action::CompleteApps,
+				"complete-configs" => return Ok(self.action(Action::CompleteConfigs)),
+				_ => unreachable!("option --{} is not handled", opt.long),
 			}
 		}
 
-		if !is_config_provided {
-			self.config.apply_file(&Self::default_config_path()?)?;
+		self.init_logger()?;
+		self.parse_positionals(positionals)?;
+
+		match self.action {
+			Action::Help | Action::Version | Action::PrintSchema => return Ok(self),
+			Action::Init => {
+				self.config.path = Some(match config_path {
+					Some(path) => path,
+					None => Self::config_dir()?.join("dotbackup.yml"),
+				});
+				return Ok(self);
+			}
+			_ => (),
 		}
 
+		let config_path = match config_path {
+			Some(path) => path,
+			None => Self::default_config_path()?,
+		};
+		trace!("using configuration {}", config_path.display());
+		if matches!(self.action, Action::Check) {
+			// `check` parses the file itself to report every problem
+			self.config.path = Some(config_path);
+			return Ok(self);
+		}
+		self.config.apply_file(&config_path)?;
+		// `verbose` in the configuration is the same as `-v`, but `-q` wins
+		if self.log_level == Some(Level::Quiet) {
+			self.config.verbose = false;
+		}
+		self.config.apply_verbose();
+
 		Ok(self)
 	}
 
+	/// Set the action by the command and parse its positional arguments
+	fn parse_positionals(&mut self, positionals: Vec<String>) -> Result<()> {
+		let Some(command) = self.command else {
+			self.parse_selectors(positionals);
+			return Ok(());
+		};
+		let mut positionals = positionals.into_iter();
+
+		self.action = match command.name {
+			"backup" | "setup" | "status" | "diff" | "dump-config" => {
+				self.parse_selectors(positionals.by_ref().collect());
+				match command.name {
+					"backup" => Action::Backup,
+					"setup" => Action::Setup,
+					"status" => Action::Status,
+					"diff" => Action::Diff,
+					_ => Action::DumpConfig,
+				}
+			}
+			"add" | "remove" => {
+				let app = positionals
+					.next()
+					.or(self.recipe.clone().filter(|_| command.name == "add"))
+					.ok_or(arg_error!(
+						"expected an application name after {}",
+						command.name
+					))?;
+				let files: Vec<PathBuf> = positionals.by_ref().map(PathBuf::from).collect();
+				if command.name == "remove" {
+					Action::Remove(app, files)
+				} else if files.is_empty() && self.recipe.is_none() {
+					return Err(arg_error!("expected file paths after add {app}"));
+				} else {
+					Action::Add(app, files)
+				}
+			}
+			"help" => {
+				self.command = positionals
+					.next()
+					.map(|name| args::command(&name))
+					.transpose()?;
+				Action::Help
+			}
+			"list" => Action::List,
+			"check" => Action::Check,
+			"print-schema" => Action::PrintSchema,
+			"init" => Action::Init,
+			"version" => Action::Version,
+			_ => unreachable!("command {} is not handled", command.name),
+		};
+
+		match positionals.next() {
+			Some(arg) => Err(arg_error!(
+				"unexpected argument {arg:?} for command {}",
+				command.name
+			)),
+			None => Ok(()),
+		}
+	}
+
+	/// Parse app selectors, `!` excludes apps
+	fn parse_selectors(&mut self, positionals: Vec<String>) {
+		for arg in positionals {
+			if let Some(selector) = arg.strip_prefix('!') {
+				self.config.excluded_apps.push(selector.to_string());
+			} else {
+				self.config.selected_apps.push(arg);
+			}
+		}
+	}
+
 	#[allow(clippy::unit_arg)]
 	pub fn run(&self) -> Result<()> {
-		match self.action {
-			Action::Backup => self.config.backup(),
-			Action::Setup => self.config.setup(),
+		match &self.action {
+			Action::Backup => self.report("backup", |reporter| self.config.backup_with(reporter)),
+			Action::Setup => self.report("setup", |reporter| self.config.setup_with(reporter)),
 			Action::Help => self.help(),
-			Action::List => Ok(self.config.list_apps()),
+			Action::List => Ok(self.list()),
+			Action::Status => self.status(),
+			Action::Diff => self.diff(),
 			Action::Version => Ok(println!("{} {VERSION}", self.name)),
-			Action::DumpConfig => Ok(print!("{}", self.config)),
+			Action::DumpConfig => Ok(print!("{}", self.config.dump(self.format)?)),
+			Action::Check => self.check(),
+			Action::Init => self.init(),
+			Action::PrintSchema => Ok(println!("{}", Config::json_schema())),
+			Action::Add(app, files) => self.add(app, files),
+			Action::Remove(app, files) => self.remove(app, files),
+			Action::Completions(shell) => Ok(print!("{}", completions::script(*shell))),
+			Action::CompleteApps => Ok(self.complete_apps()),
+			Action::CompleteConfigs => Self::complete_configs(),
 		}
 	}
 
-	/// Print help message.
+	/// Check the configuration file and print problems found.
+	pub fn check(&self) -> Result<()> {
+		let path = self
+			.config
+			.path
+			.as_ref()
+			.ok_or(arg_error!("no configuration file to check"))?;
+		let source = fs::read_to_string(path).map_err(|e| Error::io("read", path, e))?;
+		let diagnostics = Config::check(&source, Format::from_path(path))?;
+
+		for diagnostic in &diagnostics {
+			println!("{}:{diagnostic}", path.display());
+		}
+
+		if diagnostics.is_empty() {
+			info!("No problem found in {}", path.display());
+			Ok(())
+		} else {
+			Err(config_error!(
+				"{} problems found in {}",
+				diagnostics.len(),
+				path.display()
+			))
+		}
+	}
+
+	/// Print help message of dotbackup, dotsetup or the command.
 	pub fn help(&self) -> Result<()> {
-		let clean_help = match self.name {
-			Name::Dotbackup => "Delete old backup files before backup",
-			Name::Dotsetup => "Delete old configuration files before setup",
+		let config_path = Self::config_dir()?.join("<CONFIG>.{yml,toml,json}");
+		let config_path = config_path.display().to_string();
+		let options = |command: Option<&str>, legacy: bool| {
+			args::options_help(
+				OPTIONS.iter().filter(|opt| {
+					opt.applies_to(command) && legacy == (opt.scope == Scope::Legacy)
+				}),
+				&config_path,
+			)
 		};
-		let config_path = Self::config_dir()?.join("<CONFIG>.yml");
+		let selection = "\
+Select applications by name, or all applications tagged with <TAG> by @<TAG>.
+Exclude applications by !<APP> or !@<TAG>.
+";
 
-		print!(
-			"\
-Dotfile backup utility (dotbackup / dotsetup)
+		let command = self.command.filter(|command| command.name != "help");
+		match (self.name, command) {
+			(Name::Dotsetup, _) => print!(
+				"\
+Dotfile setup utility, the same as 'dotbackup setup'
 
-Usage: {name} [OPTIONS] [APPS]
+Usage: dotsetup [OPTIONS] [APPS]...
 
+{selection}
 Options:
-  -h, --help                     Print help
-  -f, --file <PATH>              Use configuration file at <PATH>
-  -c, --config <CONFIG>          Use configuration file at {config_path}
-  -l, --list                     List all applications and exit
-      --clean                    {clean_help}
-  -V, --version                  Print version info and exit
-  -v, --verbose                  Use verbose output
-      --dump-config              Print parsed configuration
+{}
+Legacy options:
+{}
+See 'man dotsetup' for more information.
+",
+				options(Some("setup"), false),
+				options(None, true),
+			),
+			(Name::Dotbackup, Some(command)) => {
+				let selection = match command.args {
+					"[APPS]..." => format!("\n{selection}"),
+					_ => String::new(),
+				};
+				print!(
+					"\
+{about}
 
+Usage: dotbackup [OPTIONS] {name} {args}
+{selection}
+Options:
+{options}
+See 'man dotbackup' for more information.
+",
+					about = command.about,
+					name = command.name,
+					args = command.args,
+					options = options(Some(command.name), false),
+				);
+			}
+			(Name::Dotbackup, None) => print!(
+				"\
+Dotfile backup utility (dotbackup / dotsetup)
+
+Usage: dotbackup [OPTIONS] [COMMAND] [ARGS]...
+
+Commands:
+{}
+Without a command, dotbackup backs up applications given as arguments.
+Arguments after -- are never taken as commands.
+
+{selection}
+Options:
+{}
+Legacy options:
+{}
+Run 'dotbackup help <COMMAND>' for options of a command.
 See 'man dotbackup' and 'man dotsetup' for more information.
 ",
-			name = self.name,
-			config_path = config_path.display()
-		);
+				args::commands_help(),
+				options(Some("backup"), false),
+				options(None, true),
+			),
+		}
 
 		Ok(())
 	}
@@ -1,3 +1,6 @@
+use super::completions::Shell;
+use std::path::PathBuf;
+
 #[derive(Debug, Clone, Default)]
 pub enum Action {
 	#[default]
@@ -5,6 +8,23 @@ pub enum Action {
 	Setup,
 	Help,
 	List,
+	/// Compare files of apps with their backups
+	Status,
+	/// Show what backup, or setup, would change
+	Diff,
 	Version,
 	DumpConfig,
+	Check,
+	PrintSchema,
+	Init,
+	/// Add files to an app, creating the app if needed
+	Add(String, Vec<PathBuf>),
+	/// Remove files from an app, or the app itself if no file is given
+	Remove(String, Vec<PathBuf>),
+	/// Print completion script for a shell
+	Completions(Shell),
+	/// Print app names and tags for completion
+	CompleteApps,
+	/// Print configuration names for completion
+	CompleteConfigs,
 }
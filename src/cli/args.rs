@@ -0,0 +1,443 @@
+//! Command-line syntax: subcommands, options and their help.
+
+use crate::{arg_error, did_you_mean, error::Result, suggest};
+use std::fmt::Write;
+
+/// A subcommand of dotbackup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command {
+	pub name: &'static str,
+	/// positional arguments in the usage line
+	pub args: &'static str,
+	pub about: &'static str,
+}
+
+pub const COMMANDS: &[Command] = &[
+	Command {
+		name: "backup",
+		args: "[APPS]...",
+		about: "Back up applications (the default)",
+	},
+	Command {
+		name: "setup",
+		args: "[APPS]...",
+		about: "Set up applications from backups (same as dotsetup)",
+	},
+	Command {
+		name: "list",
+		args: "",
+		about: "List all applications",
+	},
+	Command {
+		name: "status",
+		args: "[APPS]...",
+		about: "Show whether files of applications match their backups",
+	},
+	Command {
+		name: "diff",
+		args: "[APPS]...",
+		about: "Show files a backup, or setup with --setup, would change",
+	},
+	Command {
+		name: "check",
+		args: "",
+		about: "Check configuration for problems",
+	},
+	Command {
+		name: "dump-config",
+		args: "[APPS]...",
+		about: "Print parsed configuration",
+	},
+	Command {
+		name: "print-schema",
+		args: "",
+		about: "Print JSON Schema of configuration",
+	},
+	Command {
+		name: "init",
+		args: "",
+		about: "Create a configuration with dotfiles found",
+	},
+	Command {
+		name: "add",
+		args: "<APP> <PATH>...",
+		about: "Add files to an application in the configuration",
+	},
+	Command {
+		name: "remove",
+		args: "<APP> [PATH]...",
+		about: "Remove files, or an application, from the configuration",
+	},
+	Command {
+		name: "help",
+		args: "[COMMAND]",
+		about: "Print help of dotbackup or <COMMAND>",
+	},
+	Command {
+		name: "version",
+		args: "",
+		about: "Print version info",
+	},
+];
+
+/// Return the command called `name`
+pub fn command(name: &str) -> Result<&'static Command> {
+	COMMANDS
+		.iter()
+		.find(|command| command.name == name)
+		.ok_or_else(|| {
+			arg_error!(
+				"unknown command: {name}{}",
+				did_you_mean(name, COMMANDS.iter().map(|command| command.name))
+			)
+		})
+}
+
+/// Which commands an option applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+	/// all commands
+	Global,
+	/// only the listed commands
+	Commands(&'static [&'static str]),
+	/// only without a command, kept for compatibility
+	Legacy,
+	/// all commands, but not shown in help, used by completion scripts
+	Hidden,
+}
+
+/// An option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opt {
+	pub short: Option<char>,
+	pub long: &'static str,
+	/// name of the value, `None` if the option is a flag
+	pub value: Option<&'static str>,
+	pub help: &'static str,
+	pub scope: Scope,
+}
+
+impl Opt {
+	/// Whether the option applies to `command`, `None` means no command
+	#[must_use]
+	pub fn applies_to(&self, command: Option<&str>) -> bool {
+		match (self.scope, command) {
+			(Scope::Global | Scope::Hidden, _) | (_, None) => true,
+			(Scope::Commands(commands), Some(command)) => commands.contains(&command),
+			(Scope::Legacy, Some(_)) => false,
+		}
+	}
+
+	/// Return the option as it is shown in help, e.g., `-f, --file <PATH>`
+	fn usage(&self) -> String {
+		let short = self.short.map_or("    ".to_string(), |c| format!("-{c}, "));
+		let value = self
+			.value
+			.map_or(String::new(), |value| format!(" <{value}>"));
+		format!("{short}--{}{value}", self.long)
+	}
+}
+
+const BACKUP_SETUP: Scope = Scope::Commands(&["backup", "setup"]);
+
+pub const OPTIONS: &[Opt] = &[
+	Opt {
+		short: Some('h'),
+		long: "help",
+		value: None,
+		help: "Print help",
+		scope: Scope::Global,
+	},
+	Opt {
+		short: Some('f'),
+		long: "file",
+		value: Some("PATH"),
+		help: "Use configuration file at <PATH>",
+		scope: Scope::Global,
+	},
+	Opt {
+		short: Some('c'),
+		long: "config",
+		value: Some("CONFIG"),
+		help: "Use configuration file at {config_path}",
+		scope: Scope::Global,
+	},
+	Opt {
+		short: Some('v'),
+		long: "verbose",
+		value: None,
+		help: "Use verbose output, twice to also trace hooks",
+		scope: Scope::Global,
+	},
+	Opt {
+		short: Some('q'),
+		long: "quiet",
+		value: None,
+		help: "Print only errors",
+		scope: Scope::Global,
+	},
+	Opt {
+		short: None,
+		long: "log-file",
+		value: Some("PATH"),
+		help: "Also write log messages to <PATH>",
+		scope: Scope::Global,
+	},
+	Opt {
+		short: None,
+		long: "strict",
+		value: None,
+		help: "Fail on unknown configuration keys",
+		scope: Scope::Global,
+	},
+	Opt {
+		short: Some('V'),
+		long: "version",
+		value: None,
+		help: "Print version info and exit",
+		scope: Scope::Global,
+	},
+	Opt {
+		short: None,
+		long: "completions",
+		value: Some("SHELL"),
+		help: "Print completion script for <SHELL> (bash, zsh or fish)",
+		scope: Scope::Global,
+	},
+	Opt {
+		short: None,
+		long: "complete-apps",
+		value: None,
+		help: "Print app names and tags",
+		scope: Scope::Hidden,
+	},
+	Opt {
+		short: None,
+		long: "complete-configs",
+		value: None,
+		help: "Print configuration names",
+		scope: Scope::Hidden,
+	},
+	Opt {
+		short: Some('x'),
+		long: "exclude",
+		value: Some("APP"),
+		help: "Do not select <APP>, which may also be @<TAG>",
+		scope: Scope::Commands(&["backup", "setup", "status", "diff"]),
+	},
+	Opt {
+		short: None,
+		long: "clean",
+		value: None,
+		help: "Delete old files before copying",
+		scope: Scope::Commands(&["backup", "setup", "diff"]),
+	},
+	Opt {
+		short: Some('j'),
+		long: "jobs",
+		value: Some("N"),
+		help: "Process up to <N> applications concurrently",
+		scope: BACKUP_SETUP,
+	},
+	Opt {
+		short: None,
+		long: "no-progress",
+		value: None,
+		help: "Do not show progress of copying files",
+		scope: BACKUP_SETUP,
+	},
+	Opt {
+		short: None,
+		long: "strict-missing",
+		value: None,
+		help: "Fail if any file of an app is not found",
+		scope: BACKUP_SETUP,
+	},
+	Opt {
+		short: None,
+		long: "setup",
+		value: None,
+		help: "Show changes of setup instead of backup",
+		scope: Scope::Commands(&["diff"]),
+	},
+	Opt {
+		short: None,
+		long: "format",
+		value: Some("FORMAT"),
+		help: "Use <FORMAT> (yaml, toml or json)",
+		scope: Scope::Commands(&["dump-config"]),
+	},
+	Opt {
+		short: None,
+		long: "output",
+		value: Some("OUTPUT"),
+		help: "Print results in <OUTPUT> (text or json)",
+		scope: Scope::Commands(&["backup", "setup", "list", "status", "diff"]),
+	},
+	Opt {
+		short: Some('y'),
+		long: "yes",
+		value: None,
+		help: "Do not ask before adding apps",
+		scope: Scope::Commands(&["init"]),
+	},
+	Opt {
+		short: None,
+		long: "backup",
+		value: None,
+		help: "Back up added files right away",
+		scope: Scope::Commands(&["add"]),
+	},
+	Opt {
+		short: None,
+		long: "recipe",
+		value: Some("RECIPE"),
+		help: "Base the application on built-in <RECIPE>",
+		scope: Scope::Commands(&["add"]),
+	},
+	Opt {
+		short: Some('l'),
+		long: "list",
+		value: None,
+		help: "Same as the list command",
+		scope: Scope::Legacy,
+	},
+	Opt {
+		short: None,
+		long: "check",
+		value: None,
+		help: "Same as the check command",
+		scope: Scope::Legacy,
+	},
+	Opt {
+		short: None,
+		long: "dump-config",
+		value: None,
+		help: "Same as the dump-config command",
+		scope: Scope::Legacy,
+	},
+	Opt {
+		short: None,
+		long: "print-schema",
+		value: None,
+		help: "Same as the print-schema command",
+		scope: Scope::Legacy,
+	},
+	Opt {
+		short: None,
+		long: "init",
+		value: None,
+		help: "Same as the init command",
+		scope: Scope::Legacy,
+	},
+];
+
+/// An option found in arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found {
+	pub opt: &'static Opt,
+	/// the option as written, e.g., `-f` or `--file`
+	pub arg: String,
+	pub value: Option<String>,
+}
+
+/// Split `args` into options and positional arguments.
+///
+/// Support `--opt=value`, `-fPATH`, combined short flags like `-vy`, and
+/// `--` after which all arguments are positional. Also return the number of
+/// positional arguments before `--`.
+pub fn split(args: impl IntoIterator<Item = String>) -> Result<(Vec<Found>, Vec<String>, usize)> {
+	let mut options = Vec::new();
+	let mut positionals = Vec::new();
+	let mut args = args.into_iter();
+	let mut before = None;
+
+	while let Some(arg) = args.next() {
+		if arg == "--" {
+			before = Some(positionals.len());
+			positionals.extend(args.by_ref());
+		} else if let Some(long) = arg.strip_prefix("--") {
+			let (name, value) = match long.split_once('=') {
+				Some((name, value)) => (name, Some(value.to_string())),
+				None => (long, None),
+			};
+			let opt = OPTIONS.iter().find(|opt| opt.long == name).ok_or_else(|| {
+				let suggestion = suggest(name, OPTIONS.iter().map(|opt| opt.long));
+				arg_error!(
+					"unknown argument: {arg:?}{}",
+					suggestion.map_or(String::new(), |s| format!(" (did you mean \"--{s}\"?)"))
+				)
+			})?;
+			let arg = format!("--{name}");
+			let value = match (opt.value, value) {
+				(Some(_), Some(value)) => Some(value),
+				(Some(name), None) => Some(
+					args.next()
+						.ok_or(arg_error!("expected <{name}> after option {arg}"))?,
+				),
+				(None, Some(_)) => return Err(arg_error!("option {arg} does not take a value")),
+				(None, None) => None,
+			};
+			options.push(Found { opt, arg, value });
+		} else if let Some(shorts) = arg.strip_prefix('-')
+			&& !shorts.is_empty()
+		{
+			for (i, c) in shorts.char_indices() {
+				let opt = OPTIONS
+					.iter()
+					.find(|opt| opt.short == Some(c))
+					.ok_or(arg_error!("unknown argument: \"-{c}\""))?;
+				let arg = format!("-{c}");
+				if opt.value.is_none() {
+					options.push(Found {
+						opt,
+						arg,
+						value: None,
+					});
+					continue;
+				}
+
+				let inline = &shorts[i + c.len_utf8()..];
+				let value = if inline.is_empty() {
+					args.next().ok_or(arg_error!(
+						"expected <{}> after option {arg}",
+						opt.value.unwrap_or_default()
+					))?
+				} else {
+					inline.strip_prefix('=').unwrap_or(inline).to_string()
+				};
+				options.push(Found {
+					opt,
+					arg,
+					value: Some(value),
+				});
+				break;
+			}
+		} else if arg == "-" {
+			return Err(arg_error!("unknown argument: {arg:?}"));
+		} else {
+			positionals.push(arg);
+		}
+	}
+
+	let before = before.unwrap_or(positionals.len());
+	Ok((options, positionals, before))
+}
+
+/// Return help lines of `options`, with `{config_path}` replaced
+pub fn options_help<'a>(options: impl Iterator<Item = &'a Opt>, config_path: &str) -> String {
+	options.fold(String::new(), |mut ret, opt| {
+		let help = opt.help.replace("{config_path}", config_path);
+		let _ = writeln!(ret, "  {:<31}{help}", opt.usage());
+		ret
+	})
+}
+
+/// Return help lines of all commands
+#[must_use]
+pub fn commands_help() -> String {
+	COMMANDS.iter().fold(String::new(), |mut ret, command| {
+		let _ = writeln!(ret, "  {:<31}{}", command.name, command.about);
+		ret
+	})
+}
@@ -0,0 +1,324 @@
+//! Shell completion scripts, generated from `COMMANDS` and `OPTIONS`.
+//!
+//! Scripts complete app names and tags with the hidden `--complete-apps`
+//! query, passing `-f` and `-c` already on the command line, and configuration
+//! names with `--complete-configs`.
+
+use super::{
+	Cli, Format,
+	args::{COMMANDS, OPTIONS, Opt, Scope},
+};
+use crate::{arg_error, error::Result};
+use std::{collections::BTreeSet, fmt::Write, fs, str::FromStr};
+
+/// A shell to generate completion script for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+	Bash,
+	Zsh,
+	Fish,
+}
+
+impl FromStr for Shell {
+	type Err = crate::error::Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match s {
+			"bash" => Ok(Self::Bash),
+			"zsh" => Ok(Self::Zsh),
+			"fish" => Ok(Self::Fish),
+			_ => Err(arg_error!(
+				"unknown shell: {s:?}, expected bash, zsh or fish"
+			)),
+		}
+	}
+}
+
+/// Options shown in completion
+fn options() -> impl Iterator<Item = &'static Opt> {
+	OPTIONS.iter().filter(|opt| opt.scope != Scope::Hidden)
+}
+
+/// Return values of options with a fixed set of values
+fn choices(value: &str) -> Option<&'static str> {
+	match value {
+		"FORMAT" => Some("yaml toml json"),
+		"SHELL" => Some("bash zsh fish"),
+		"OUTPUT" => Some("text json"),
+		_ => None,
+	}
+}
+
+fn bash() -> String {
+	let mut words: Vec<String> = options().map(|opt| format!("--{}", opt.long)).collect();
+	words.extend(options().filter_map(|opt| opt.short.map(|c| format!("-{c}"))));
+	let commands: Vec<&str> = COMMANDS.iter().map(|command| command.name).collect();
+
+	let mut values = String::new();
+	for opt in options() {
+		let Some(value) = opt.value else {
+			continue;
+		};
+		let pattern = match opt.short {
+			Some(c) => format!("-{c}|--{}", opt.long),
+			None => format!("--{}", opt.long),
+		};
+		let reply = match (value, choices(value)) {
+			(_, Some(choices)) => format!("$(compgen -W \"{choices}\" -- \"$cur\")"),
+			("PATH", _) => "$(compgen -f -- \"$cur\")".to_string(),
+			("CONFIG", _) => {
+				"$(compgen -W \"$(\"$cmd\" --complete-configs 2>/dev/null)\" -- \"$cur\")"
+					.to_string()
+			}
+			("APP", _) => "$(compgen -W \"$apps\" -- \"$cur\")".to_string(),
+			_ => String::new(),
+		};
+		let _ = writeln!(values, "\t\t{pattern}) COMPREPLY=({reply}); return ;;");
+	}
+
+	format!(
+		r#"# bash completion for dotbackup and dotsetup
+
+_dotbackup() {{
+	local cmd="${{COMP_WORDS[0]}}" cur="${{COMP_WORDS[COMP_CWORD]}}"
+	local prev="${{COMP_WORDS[COMP_CWORD-1]}}" config=() i
+	for ((i = 1; i < COMP_CWORD; i++)); do
+		case "${{COMP_WORDS[i]}}" in
+		-f|--file|-c|--config) config+=("${{COMP_WORDS[i]}}" "${{COMP_WORDS[i+1]}}") ;;
+		--file=*|--config=*) config+=("${{COMP_WORDS[i]}}") ;;
+		esac
+	done
+	local apps
+	apps="$("$cmd" "${{config[@]}}" --complete-apps 2>/dev/null)"
+
+	case "$prev" in
+{values}	esac
+
+	if [[ "$cur" == -* ]]; then
+		COMPREPLY=($(compgen -W "{words}" -- "$cur"))
+	elif [[ "${{cmd##*/}}" == dotbackup ]]; then
+		COMPREPLY=($(compgen -W "{commands} $apps" -- "$cur"))
+	else
+		COMPREPLY=($(compgen -W "$apps" -- "$cur"))
+	fi
+}}
+
+complete -F _dotbackup dotbackup dotsetup
+"#,
+		words = words.join(" "),
+		commands = commands.join(" "),
+	)
+}
+
+fn zsh_escape(s: &str) -> String {
+	s.replace('\'', "'\\''")
+		.replace('[', "\\[")
+		.replace(']', "\\]")
+		.replace(':', "\\:")
+}
+
+fn zsh() -> String {
+	let mut specs = String::new();
+	for opt in options() {
+		let help = zsh_escape(&opt.help.replace("{config_path}", "<CONFIG>"));
+		let action = opt.value.map_or(String::new(), |value| {
+			let action = match (value, choices(value)) {
+				(_, Some(choices)) => format!("({choices})"),
+				("PATH", _) => "_files".to_string(),
+				("CONFIG", _) => "_dotbackup_configs".to_string(),
+				("APP", _) => "_dotbackup_apps".to_string(),
+				_ => " ".to_string(),
+			};
+			format!(":{}:{action}", value.to_lowercase())
+		});
+		let (short, long) = match opt.value {
+			Some(_) => ("+", "="),
+			None => ("", ""),
+		};
+		let spec = match opt.short {
+			Some(c) => format!(
+				"'(-{c} --{name})'{{-{c}{short},--{name}{long}}}'[{help}]{action}'",
+				name = opt.long
+			),
+			None => format!("'--{}{long}[{help}]{action}'", opt.long),
+		};
+		let _ = writeln!(specs, "\t\t{spec} \\");
+	}
+
+	let commands = COMMANDS.iter().fold(String::new(), |mut ret, command| {
+		let _ = writeln!(ret, "\t\t'{}:{}'", command.name, zsh_escape(command.about));
+		ret
+	});
+
+	format!(
+		r#"#compdef dotbackup dotsetup
+# zsh completion for dotbackup and dotsetup
+
+typeset -ga _dotbackup_config
+
+_dotbackup_configs() {{
+	local -a configs
+	configs=(${{(f)"$($service --complete-configs 2>/dev/null)"}})
+	_describe 'configuration' configs
+}}
+
+_dotbackup_apps() {{
+	local -a apps
+	apps=(${{(f)"$($service $_dotbackup_config --complete-apps 2>/dev/null)"}})
+	compadd -a apps
+}}
+
+_dotbackup_args() {{
+	local -a commands
+	commands=(
+{commands}	)
+	if [[ $service == dotbackup && CURRENT -eq 1 ]]; then
+		_describe 'command' commands
+	fi
+	_dotbackup_apps
+}}
+
+_dotbackup() {{
+	local i
+	_dotbackup_config=()
+	for ((i = 2; i < CURRENT; i++)); do
+		case ${{words[i]}} in
+		-f|--file|-c|--config) _dotbackup_config+=(${{words[i]}} ${{words[i+1]}}) ;;
+		--file=*|--config=*) _dotbackup_config+=(${{words[i]}}) ;;
+		esac
+	done
+
+	_arguments -s -S \
+{specs}		'*::argument:_dotbackup_args'
+}}
+
+if [[ $funcstack[1] == _dotbackup ]]; then
+	_dotbackup "$@"
+else
+	compdef _dotbackup dotbackup dotsetup
+fi
+"#
+	)
+}
+
+fn fish_escape(s: &str) -> String {
+	s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish() -> String {
+	let mut options = String::new();
+	for opt in self::options() {
+		let mut line = "\tcomplete -c $cmd".to_string();
+		if let Some(c) = opt.short {
+			let _ = write!(line, " -s {c}");
+		}
+		let _ = write!(line, " -l {}", opt.long);
+		if let Some(value) = opt.value {
+			let _ = match (value, choices(value)) {
+				(_, Some(choices)) => write!(line, " -x -a '{choices}'"),
+				("PATH", _) => write!(line, " -r -F"),
+				("CONFIG", _) => write!(line, " -x -a '(__dotbackup_configs)'"),
+				("APP", _) => write!(line, " -x -a '(__dotbackup_apps)'"),
+				_ => write!(line, " -x"),
+			};
+		}
+		let help = opt.help.replace("{config_path}", "<CONFIG>");
+		let _ = writeln!(options, "{line} -d '{}'", fish_escape(&help));
+	}
+
+	let commands = COMMANDS.iter().fold(String::new(), |mut ret, command| {
+		let _ = writeln!(
+			ret,
+			"complete -c dotbackup -n __fish_use_subcommand -f -a {} -d '{}'",
+			command.name,
+			fish_escape(command.about)
+		);
+		ret
+	});
+
+	format!(
+		r"# fish completion for dotbackup and dotsetup
+
+function __dotbackup_config
+	set -l tokens (commandline -opc)
+	for i in (seq 2 (count $tokens))
+		switch $tokens[$i]
+			case -f --file -c --config
+				set -l next (math $i + 1)
+				if test $next -le (count $tokens)
+					printf '%s\n' $tokens[$i] $tokens[$next]
+				end
+			case '--file=*' '--config=*'
+				printf '%s\n' $tokens[$i]
+		end
+	end
+end
+
+function __dotbackup_apps
+	set -l tokens (commandline -opc)
+	$tokens[1] (__dotbackup_config) --complete-apps 2>/dev/null
+end
+
+function __dotbackup_configs
+	set -l tokens (commandline -opc)
+	$tokens[1] --complete-configs 2>/dev/null
+end
+
+for cmd in dotbackup dotsetup
+	complete -c $cmd -f -a '(__dotbackup_apps)'
+{options}end
+
+{commands}"
+	)
+}
+
+/// Return the completion script for `shell`
+#[must_use]
+pub fn script(shell: Shell) -> String {
+	match shell {
+		Shell::Bash => bash(),
+		Shell::Zsh => zsh(),
+		Shell::Fish => fish(),
+	}
+}
+
+impl Cli {
+	/// Print names of configurations in the configuration directory
+	pub fn complete_configs() -> Result<()> {
+		let Ok(entries) = fs::read_dir(Self::config_dir()?) else {
+			return Ok(());
+		};
+
+		let names: BTreeSet<String> = entries
+			.flatten()
+			.map(|entry| entry.path())
+			.filter(|path| {
+				path.extension()
+					.and_then(|ext| ext.to_str())
+					.is_some_and(|ext| Format::ALL.iter().any(|format| format.extension() == ext))
+			})
+			.filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+			.collect();
+		for name in names {
+			println!("{name}");
+		}
+
+		Ok(())
+	}
+
+	/// Print app names and tags (as `@tag`) of the configuration
+	pub fn complete_apps(&self) {
+		let tags: BTreeSet<&String> = self
+			.config
+			.apps
+			.values()
+			.flat_map(|app| &app.tags)
+			.collect();
+		for name in self.config.apps.keys() {
+			println!("{name}");
+		}
+		for tag in tags {
+			println!("@{tag}");
+		}
+	}
+}
@@ -1,20 +1,41 @@
 mod app;
+mod check;
+mod file;
+mod format;
+mod metadata;
+mod recipe;
+mod status;
 
 pub use app::*;
+pub use check::*;
 use dirs::home_dir;
+pub use file::*;
+pub use format::*;
+pub use metadata::*;
+pub use recipe::*;
+pub use status::*;
 
+use super::Action;
 use crate::{
-	arg_error, config_error,
-	consts::colors::{GREEN, RESET},
+	arg_error, check_writable, config_error, did_you_mean,
 	error::{Error, Result},
-	expandhome, run_hooks, sys_error,
+	expandhome, find_program, log,
+	privileged::Helper,
+	reporter::{LogReporter, Reporter},
+	run_hooks,
+	summary::Summary,
+	sys_error,
 };
+use glob::Pattern;
+use schemars::{JsonSchema, schema_for};
 use serde::{Deserialize, Serialize};
 use std::{
-	collections::BTreeMap,
+	collections::{BTreeMap, BTreeSet},
 	fs::File,
 	io::Read,
 	path::{Path, PathBuf},
+	sync::{Condvar, Mutex},
+	thread,
 };
 
 #[allow(clippy::trivially_copy_pass_by_ref)]
@@ -22,59 +43,125 @@ fn is_false(value: &bool) -> bool {
 	!value
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_sequential(jobs: &usize) -> bool {
+	*jobs <= 1
+}
+
+/// Progress of apps run by `Config::for_each_app`
+struct Schedule {
+	started: Vec<bool>,
+	done: Vec<bool>,
+	failed: bool,
+	errors: Vec<(usize, Error)>,
+}
+
+impl Schedule {
+	fn new(n: usize) -> Self {
+		Self {
+			started: vec![false; n],
+			done: vec![false; n],
+			failed: false,
+			errors: Vec::new(),
+		}
+	}
+
+	/// Return the first app not started whose dependencies are all done
+	fn next(&self, deps: &[Vec<usize>]) -> Option<usize> {
+		(0..self.started.len())
+			.find(|&i| !self.started[i] && deps[i].iter().all(|&dep| self.done[dep]))
+	}
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
 	// NOTE: CLI args may change these, be sure to consider them in `apply_file`
 	//
 	// empty means select all apps
 	#[serde(skip)]
 	pub selected_apps: Vec<String>,
+	// apps or `@tag`s not to be selected
+	#[serde(skip)]
+	pub excluded_apps: Vec<String>,
+	/// path of the configuration file, if loaded from a file
+	#[serde(skip)]
+	pub path: Option<PathBuf>,
+	/// delete old files in the destination before backup and setup
 	#[serde(default)]
 	#[serde(skip_serializing_if = "is_false")]
 	pub clean: bool,
+	/// print more logs, the same as `-v`
 	#[serde(default)]
 	#[serde(skip_serializing_if = "is_false")]
 	pub verbose: bool,
+	/// fail on unknown keys instead of ignoring them
+	#[serde(default)]
+	#[serde(skip_serializing_if = "is_false")]
+	pub strict: bool,
+	/// fail if any file is not found, overriding `missing` of apps
+	#[serde(default)]
+	#[serde(skip_serializing_if = "is_false")]
+	pub strict_missing: bool,
+	/// command to run file operations of privileged apps as root, default is
+	/// sudo or doas found in PATH
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub privilege_helper: Option<String>,
+	/// number of apps to process concurrently, 0 means not set (same as 1)
+	#[serde(default)]
+	#[serde(skip_serializing_if = "is_sequential")]
+	pub jobs: usize,
 
 	/// dotfile root directory, default is the home directory
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub dotfile_root: Option<PathBuf>,
 
+	/// directory where backup files are stored
 	pub backup_dir: PathBuf,
 
+	/// directory where backup files are stored on Linux
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub backup_dir_linux: Option<PathBuf>,
 
+	/// directory where backup files are stored on macOS
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub backup_dir_macos: Option<PathBuf>,
 
+	/// directory where backup files are stored on Windows
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub backup_dir_windows: Option<PathBuf>,
 
+	/// glob patterns of files to be ignored in all apps
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub ignore: Vec<String>,
 
+	/// apps to be backed up and set up, by name
 	#[serde(default)]
 	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
 	pub apps: BTreeMap<String, App>,
 
+	/// scripts to run before backup
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub pre_backup: Vec<String>,
 
+	/// scripts to run after backup
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub post_backup: Vec<String>,
 
+	/// scripts to run before setup
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub pre_setup: Vec<String>,
 
+	/// scripts to run after setup
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub post_setup: Vec<String>,
@@ -87,14 +174,38 @@ impl Config {
 	}
 
 	pub fn from_file(path: &Path) -> Result<Self> {
-		let mut config_file =
-			File::open(path).map_err(|e| sys_error!("failed to open {}: {e}", path.display()))?;
+		Self::from_file_strict(path, false)
+	}
+
+	/// Like `from_file`, but fail on unknown keys if `strict` is true
+	pub fn from_file_strict(path: &Path, strict: bool) -> Result<Self> {
+		let mut config_file = File::open(path).map_err(|e| Error::io("open", path, e))?;
 		let mut content = String::new();
 		config_file
 			.read_to_string(&mut content)
-			.map_err(|e| sys_error!("{e}"))?;
+			.map_err(|e| Error::io("read", path, e))?;
+
+		Self::parse_as(&content, Format::from_path(path), strict).map_err(|e| e.with_path(path))
+	}
+
+	/// Parse configuration from YAML `source`.
+	///
+	/// If `strict` is true or `strict` is set in `source`, unknown keys are
+	/// errors instead of being ignored.
+	pub fn parse(source: &str, strict: bool) -> Result<Self> {
+		Self::parse_as(source, Format::Yaml, strict)
+	}
 
-		Self::try_from(content.as_str())
+	/// Like `parse`, but `source` is in `format`
+	pub fn parse_as(source: &str, format: Format, strict: bool) -> Result<Self> {
+		let mut config: Self = format.parse(source)?;
+		if strict || config.strict {
+			check_unknown_keys(source, format)?;
+		}
+		config.apply_recipes()?;
+		config.resolve_dependencies(&config.apps.keys().cloned().collect::<Vec<_>>())?;
+
+		Ok(config)
 	}
 
 	/// Return expanded `backup_dir`
@@ -119,13 +230,261 @@ impl Config {
 		expandhome(path)
 	}
 
-	#[must_use]
-	pub fn get_selected_apps(&self) -> Vec<String> {
-		if self.selected_apps.is_empty() {
+	/// Return app names matched by `selector`, which is an app name or `@tag`
+	pub fn match_apps(&self, selector: &str) -> Result<Vec<String>> {
+		if let Some(tag) = selector.strip_prefix('@') {
+			let names: Vec<String> = self
+				.apps
+				.iter()
+				.filter(|(_, app)| app.tags.iter().any(|t| t == tag))
+				.map(|(name, _)| name.clone())
+				.collect();
+
+			if names.is_empty() {
+				let tags = self.apps.values().flat_map(|app| &app.tags);
+				Err(arg_error!(
+					"no app is tagged with {tag:?}{}",
+					did_you_mean(tag, tags.map(String::as_str))
+				))
+			} else {
+				Ok(names)
+			}
+		} else if self.apps.contains_key(selector) {
+			Ok(vec![selector.to_string()])
+		} else {
+			Err(arg_error!(
+				"app not found: {selector}{}",
+				did_you_mean(selector, self.apps.keys().map(String::as_str))
+			))
+		}
+	}
+
+	/// Return selected apps and their dependencies in dependency order, without
+	/// excluded apps. It is an error if a selected app depends on an excluded
+	/// app.
+	pub fn get_selected_apps(&self) -> Result<Vec<String>> {
+		let mut problems = Vec::new();
+		let ret = self.select_apps(&mut problems);
+		Error::combine(problems)?;
+
+		Ok(ret)
+	}
+
+	/// Like `get_selected_apps`, but push problems to `problems` and skip
+	/// invalid selectors
+	fn select_apps(&self, problems: &mut Vec<Error>) -> Vec<String> {
+		let mut match_all = |selectors: &[String]| {
+			let mut names = Vec::new();
+			for selector in selectors {
+				match self.match_apps(selector) {
+					Ok(matched) => names.extend(matched),
+					Err(e) => problems.push(e),
+				}
+			}
+			names
+		};
+
+		let names = if self.selected_apps.is_empty() {
 			self.apps.keys().cloned().collect()
 		} else {
-			self.selected_apps.clone()
+			match_all(&self.selected_apps)
+		};
+		let excluded: BTreeSet<String> = match_all(&self.excluded_apps).into_iter().collect();
+		let names: Vec<String> = names
+			.into_iter()
+			.filter(|name| !excluded.contains(name))
+			.collect();
+
+		let ret = self.resolve_dependencies(&names).unwrap_or_else(|e| {
+			problems.push(e);
+			Vec::new()
+		});
+		for name in &ret {
+			for dep in &self.apps[name].depends_on {
+				if excluded.contains(dep) {
+					problems.push(arg_error!(
+						"app {dep} is excluded, but app {name} depends on it"
+					));
+				}
+			}
 		}
+
+		ret
+	}
+
+	/// Validate the whole run of `action` (backup or setup) before anything is
+	/// executed, return selected apps like `get_selected_apps`.
+	///
+	/// All problems are reported at once, including invalid app selection,
+	/// invalid glob patterns, files outside of the dotfile root, unwritable
+	/// backup directories and missing shell for hooks.
+	pub fn preflight(&self, action: &Action) -> Result<Vec<String>> {
+		let mut problems = Vec::new();
+		let names = self.select_apps(&mut problems);
+		let dotfile_root = self.get_dotfile_root();
+		let mut backup_dirs = BTreeSet::from([self.get_backup_dir()]);
+		let mut has_hooks = match action {
+			Action::Setup => !self.pre_setup.is_empty() || !self.post_setup.is_empty(),
+			_ => !self.pre_backup.is_empty() || !self.post_backup.is_empty(),
+		};
+
+		for pattern in &self.ignore {
+			if let Err(e) = Pattern::new(pattern) {
+				problems.push(config_error!("invalid glob pattern in ignore: {e}"));
+			}
+		}
+
+		for name in &names {
+			let app = &self.apps[name];
+
+			for pattern in &app.ignore {
+				if let Err(e) = Pattern::new(pattern) {
+					problems.push(config_error!(
+						"invalid glob pattern in apps.{name}.ignore: {e}"
+					));
+				}
+			}
+			for pattern in app.modes.keys() {
+				if let Err(e) = Pattern::new(pattern) {
+					problems.push(config_error!(
+						"invalid glob pattern in apps.{name}.modes: {e}"
+					));
+				}
+			}
+
+			for entry in app.get_files() {
+				let file = expandhome(entry.path());
+				if entry.dest().is_none() && !file.starts_with(&dotfile_root) {
+					problems.push(config_error!(
+						"the file ({}) of app {name} is expected to be under the dotfile root ({}) or to have a dest",
+						file.display(),
+						dotfile_root.display(),
+					));
+				}
+				if !entry.is_dest_valid() {
+					problems.push(config_error!(
+						"the dest of {} in app {name} is expected to be a relative path in the backup directory",
+						file.display(),
+					));
+				}
+				for pattern in entry.ignore() {
+					if let Err(e) = Pattern::new(pattern) {
+						problems.push(config_error!(
+							"invalid glob pattern in ignore of {} in app {name}: {e}",
+							file.display(),
+						));
+					}
+				}
+			}
+
+			backup_dirs.insert(app.get_backup_dir(self));
+			has_hooks |= match action {
+				Action::Setup => !app.pre_setup.is_empty() || !app.post_setup.is_empty(),
+				_ => !app.pre_backup.is_empty() || !app.post_backup.is_empty(),
+			};
+		}
+
+		if names.iter().any(|name| self.apps[name].privileged)
+			&& let Err(e) = Helper::new(self.privilege_helper.as_deref())
+		{
+			problems.push(e);
+		}
+
+		if !matches!(action, Action::Setup) {
+			for dir in &backup_dirs {
+				if let Err(e) = check_writable(dir) {
+					problems.push(e);
+				}
+			}
+		}
+
+		if has_hooks && find_program("sh").is_none() {
+			problems.push(sys_error!(
+				"sh is not found in PATH, which is needed by hooks"
+			));
+		}
+
+		Error::combine(problems)?;
+		Ok(names)
+	}
+
+	/// Return `names` and all their dependencies, sorted so that every app
+	/// comes after its dependencies, ties are sorted by name.
+	pub fn resolve_dependencies(&self, names: &[String]) -> Result<Vec<String>> {
+		let (ret, cycle) = self.sort_dependencies(names)?;
+		match cycle {
+			Some(cycle) => Err(config_error!(
+				"dependency cycle among apps: {}",
+				cycle.join(" -> ")
+			)),
+			None => Ok(ret),
+		}
+	}
+
+	/// Like `resolve_dependencies`, but also return a dependency cycle if
+	/// any, whose apps are left out, instead of failing
+	pub(crate) fn sort_dependencies(
+		&self,
+		names: &[String],
+	) -> Result<(Vec<String>, Option<Vec<String>>)> {
+		let mut deps: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+		let mut stack: Vec<&str> = names.iter().map(String::as_str).collect();
+
+		while let Some(name) = stack.pop() {
+			if deps.contains_key(name) {
+				continue;
+			}
+			let app = self
+				.apps
+				.get(name)
+				.ok_or(config_error!("app not found: {name}"))?;
+
+			for dep in &app.depends_on {
+				if !self.apps.contains_key(dep) {
+					return Err(config_error!("unknown dependency of app {name}: {dep}"));
+				}
+				stack.push(dep);
+			}
+			deps.insert(name, app.depends_on.iter().map(String::as_str).collect());
+		}
+
+		let mut ret = Vec::new();
+		let mut ready: BTreeSet<&str> = deps
+			.iter()
+			.filter(|(_, deps)| deps.is_empty())
+			.map(|(name, _)| *name)
+			.collect();
+		while let Some(name) = ready.pop_first() {
+			ret.push(name.to_string());
+			deps.remove(name);
+			for (dependent, deps) in &mut deps {
+				if deps.remove(name) && deps.is_empty() {
+					ready.insert(dependent);
+				}
+			}
+		}
+
+		// every app left has an unresolved dependency, follow them from any app
+		// until one repeats, the apps since its first visit form a cycle
+		let mut path: Vec<&str> = Vec::new();
+		let mut next = deps.keys().next().copied();
+		while let Some(name) = next {
+			if let Some(start) = path.iter().position(|visited| *visited == name) {
+				let mut cycle = path.split_off(start);
+				cycle.push(name);
+				return Ok((ret, Some(cycle.into_iter().map(String::from).collect())));
+			}
+			path.push(name);
+			next = deps.get(name).and_then(|deps| deps.first().copied());
+		}
+
+		Ok((ret, None))
+	}
+
+	/// Return the number of apps to process concurrently
+	#[must_use]
+	pub fn get_jobs(&self) -> usize {
+		self.jobs.max(1)
 	}
 
 	/// Return expanded `dotfile_root`
@@ -146,76 +505,254 @@ impl Config {
 		}
 	}
 
-	pub fn backup(&self) -> Result<()> {
-		let backup_dir = &self.get_backup_dir();
-		let selected_apps = self.get_selected_apps();
-
-		run_hooks(&self.pre_backup, backup_dir, "pre-backup hooks")?;
+	/// Run `f` on each app, up to `jobs` apps concurrently.
+	///
+	/// `names` must be in dependency order. An app is not started until all
+	/// of its dependencies in `names` are done. With more than one job, log
+	/// output of each app is buffered and printed as a whole after the app is
+	/// done, so that logs of different apps are not interleaved. After an app
+	/// fails, no more apps are started, and the error of the first failed app
+	/// (in the order of `names`) is returned. Errors are tagged with the app.
+	fn for_each_app<F>(&self, names: &[String], f: F) -> Result<()>
+	where
+		F: Fn(&str) -> Result<()> + Sync,
+	{
+		let f = |name: &str| f(name).map_err(|e| e.with_app(name));
+		let jobs = self.get_jobs().min(names.len());
+		if jobs <= 1 {
+			return names.iter().try_for_each(|name| f(name));
+		}
 
-		for name in &selected_apps {
-			if !self.apps.contains_key(name) {
-				return Err(arg_error!("app not found: {}", name));
+		let deps: Vec<Vec<usize>> = names
+			.iter()
+			.map(|name| {
+				self.apps.get(name).map_or(Vec::new(), |app| {
+					app.depends_on
+						.iter()
+						.filter_map(|dep| names.iter().position(|name| name == dep))
+						.collect()
+				})
+			})
+			.collect();
+		let schedule = Mutex::new(Schedule::new(names.len()));
+		let cvar = Condvar::new();
+		thread::scope(|s| {
+			for _ in 0..jobs {
+				s.spawn(|| {
+					loop {
+						let i = {
+							let mut schedule = schedule.lock().unwrap();
+							loop {
+								if schedule.failed {
+									return;
+								}
+								if let Some(i) = schedule.next(&deps) {
+									schedule.started[i] = true;
+									break i;
+								}
+								if schedule.started.iter().all(|started| *started) {
+									return;
+								}
+								schedule = cvar.wait(schedule).unwrap();
+							}
+						};
+
+						let (ret, output) = log::capture(|| f(&names[i]));
+						log::replay(output);
+
+						let mut schedule = schedule.lock().unwrap();
+						schedule.done[i] = true;
+						if let Err(e) = ret {
+							schedule.failed = true;
+							schedule.errors.push((i, e));
+						}
+						cvar.notify_all();
+					}
+				});
 			}
-			let app = &self.apps[name];
-			let highlight_name = format!("{GREEN}{name}{RESET}");
+		});
 
-			run_hooks(
+		let Schedule { mut errors, .. } = schedule.into_inner().unwrap();
+		errors.sort_by_key(|(i, _)| *i);
+		match errors.into_iter().next() {
+			Some((_, e)) => Err(e),
+			None => Ok(()),
+		}
+	}
+
+	/// Run `action` (backup or setup) of app `name` with its hooks
+	fn run_app(
+		&self,
+		action: &Action,
+		name: &str,
+		backup_dir: &Path,
+		reporter: &dyn Reporter,
+	) -> Result<()> {
+		let app = &self.apps[name];
+		let ret = match action {
+			Action::Setup => run_hooks(
+				&app.pre_setup,
+				backup_dir,
+				"pre_setup",
+				Some(name),
+				reporter,
+			)
+			.and_then(|()| app.setup(name, self, reporter))
+			.and_then(|()| {
+				run_hooks(
+					&app.post_setup,
+					backup_dir,
+					"post_setup",
+					Some(name),
+					reporter,
+				)
+			}),
+			_ => run_hooks(
 				&app.pre_backup,
 				backup_dir,
-				&format!("pre-backup hooks for {highlight_name}"),
-			)?;
+				"pre_backup",
+				Some(name),
+				reporter,
+			)
+			.and_then(|()| app.backup(name, self, reporter))
+			.and_then(|()| {
+				run_hooks(
+					&app.post_backup,
+					backup_dir,
+					"post_backup",
+					Some(name),
+					reporter,
+				)
+			}),
+		};
+		let action = match action {
+			Action::Setup => "setup",
+			_ => "backup",
+		};
+		reporter.app_finished(action, name, &ret);
 
-			self.apps[name].backup(name, self)?;
+		ret
+	}
 
-			run_hooks(
-				&app.post_backup,
-				backup_dir,
-				&format!("post-backup hooks for {highlight_name}"),
-			)?;
+	/// Ask for the password of the privilege helper once before running
+	/// `names`, if any of them is privileged
+	fn validate_privileges(&self, names: &[String], reporter: &dyn Reporter) -> Result<()> {
+		if names.iter().any(|name| self.apps[name].privileged) {
+			let helper = Helper::new(self.privilege_helper.as_deref())?;
+			if let Some(warning) = helper.warning() {
+				reporter.warning(None, &warning);
+			}
+			helper.validate()?;
 		}
 
-		run_hooks(&self.post_backup, backup_dir, "post-backup hooks")
+		Ok(())
 	}
 
-	pub fn setup(&self) -> Result<()> {
+	/// Raise the log level to verbose if `verbose` is set
+	pub(crate) fn apply_verbose(&self) {
+		if self.verbose && !log::enabled(log::Level::Verbose) {
+			log::set_level(log::Level::Verbose);
+		}
+	}
+
+	/// Run backup of selected apps, logging progress and a summary
+	pub fn backup(&self) -> Result<()> {
+		let reporter = LogReporter::new();
+		let summary = Summary::new(&reporter);
+		let ret = self.backup_with(&summary);
+		summary.print("backup");
+		ret
+	}
+
+	/// Run backup of selected apps, reporting progress to `reporter`
+	pub fn backup_with(&self, reporter: &dyn Reporter) -> Result<()> {
+		self.apply_verbose();
 		let backup_dir = &self.get_backup_dir();
-		let selected_apps = self.get_selected_apps();
+		let selected_apps = self.preflight(&Action::Backup)?;
+		self.validate_privileges(&selected_apps, reporter)?;
 
-		run_hooks(&self.pre_setup, backup_dir, "pre-setup hooks")?;
+		run_hooks(&self.pre_backup, backup_dir, "pre_backup", None, reporter)?;
 
-		for name in &selected_apps {
-			if !self.apps.contains_key(name) {
-				return Err(arg_error!("app not found: {}", name));
-			}
-			let app = &self.apps[name];
-			let highlight_name = format!("{GREEN}{name}{RESET}");
+		self.for_each_app(&selected_apps, |name| {
+			self.run_app(&Action::Backup, name, backup_dir, reporter)
+		})?;
 
-			run_hooks(
-				&app.pre_setup,
-				backup_dir,
-				&format!("pre-setup hooks for {highlight_name}"),
-			)?;
+		run_hooks(&self.post_backup, backup_dir, "post_backup", None, reporter)
+	}
 
-			self.apps[name].setup(name, self)?;
+	/// Run setup of selected apps, logging progress and a summary
+	pub fn setup(&self) -> Result<()> {
+		let reporter = LogReporter::new();
+		let summary = Summary::new(&reporter);
+		let ret = self.setup_with(&summary);
+		summary.print("setup");
+		ret
+	}
 
-			run_hooks(
-				&app.post_setup,
-				backup_dir,
-				&format!("post-setup hooks for {highlight_name}"),
-			)?;
+	/// Run setup of selected apps, reporting progress to `reporter`
+	pub fn setup_with(&self, reporter: &dyn Reporter) -> Result<()> {
+		self.apply_verbose();
+		let backup_dir = &self.get_backup_dir();
+		let selected_apps = self.preflight(&Action::Setup)?;
+		self.validate_privileges(&selected_apps, reporter)?;
+
+		run_hooks(&self.pre_setup, backup_dir, "pre_setup", None, reporter)?;
+
+		self.for_each_app(&selected_apps, |name| {
+			self.run_app(&Action::Setup, name, backup_dir, reporter)
+		})?;
+
+		run_hooks(&self.post_setup, backup_dir, "post_setup", None, reporter)
+	}
+
+	/// Return the configuration in `format`, with selected and excluded apps
+	/// as comments if `format` supports comments
+	pub fn dump(&self, format: Format) -> Result<String> {
+		let mut comments = Vec::new();
+		if !self.selected_apps.is_empty() {
+			comments.push(format!("# selected_apps: {:?}\n", self.selected_apps));
+		}
+		if !self.excluded_apps.is_empty() {
+			comments.push(format!("# excluded_apps: {:?}\n", self.excluded_apps));
+		}
+		if format == Format::Json {
+			comments.clear();
 		}
 
-		run_hooks(&self.post_setup, backup_dir, "post-setup hooks")
+		Ok(comments.concat() + &format.serialize(self)?)
+	}
+
+	/// Return the JSON Schema of the configuration format
+	///
+	/// # Panics
+	///
+	/// Should not panic
+	#[must_use]
+	pub fn json_schema() -> String {
+		serde_json::to_string_pretty(&schema_for!(Config)).unwrap()
 	}
 
 	pub fn apply_file(&mut self, path: &Path) -> Result<()> {
-		let config = Config::from_file(path)?;
+		let config = Config::from_file_strict(path, self.strict)?;
 		let clean = if self.clean { true } else { config.clean };
+		let verbose = self.verbose || config.verbose;
+		let strict = self.strict || config.strict;
+		let strict_missing = self.strict_missing || config.strict_missing;
+		let jobs = if self.jobs == 0 {
+			config.jobs
+		} else {
+			self.jobs
+		};
 
 		*self = Self {
-			verbose: self.verbose,
+			path: Some(path.to_path_buf()),
+			verbose,
 			selected_apps: self.selected_apps.clone(),
+			excluded_apps: self.excluded_apps.clone(),
 			clean,
+			strict,
+			strict_missing,
+			jobs,
 			..config
 		};
 
@@ -225,14 +762,10 @@ impl Config {
 
 impl std::fmt::Display for Config {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		if !self.selected_apps.is_empty() {
-			writeln!(f, "# selected_apps: {:?}", self.selected_apps)?;
-		}
-
 		writeln!(
 			f,
 			"{}",
-			yaml_serde::to_string(self).map_err(|_| std::fmt::Error)?
+			self.dump(Format::Yaml).map_err(|_| std::fmt::Error)?
 		)
 	}
 }
@@ -240,7 +773,6 @@ impl std::fmt::Display for Config {
 impl TryFrom<&str> for Config {
 	type Error = Error;
 	fn try_from(value: &str) -> Result<Self> {
-		let config: Self = yaml_serde::from_str(value).map_err(|e| config_error!("{e}"))?;
-		Ok(config)
+		Self::parse(value, false)
 	}
 }
@@ -1,76 +1,217 @@
-use super::Config;
+use super::{Config, FileEntry, Metadata, Metadatas, Missing, Mode, is_false};
 use crate::{
-	arg_error,
-	colors::{GREEN, RESET},
-	config_error, copy_dir_all,
-	error::Result,
-	expandhome, info, log, sys_error, warn,
+	arg_error, config_error, copy_dir_all,
+	error::{Error, Result, Type},
+	expandhome, log,
+	privileged::{Helper, remove_ignored, walk_local},
+	reporter::{Progress, Reporter},
+	set_mode, set_owner, sys_error,
 };
 use glob::Pattern;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
+	collections::{BTreeMap, HashSet},
 	fmt::Display,
 	fs::{self, create_dir_all, remove_dir_all, remove_file},
-	path::PathBuf,
+	io,
+	path::{Path, PathBuf},
 };
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct App {
+	/// name of a built-in recipe this app is based on
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub recipe: Option<String>,
+
+	/// directory where backup files of this app are stored
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub backup_dir: Option<PathBuf>,
 
+	/// directory where backup files of this app are stored on Linux
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub backup_dir_linux: Option<PathBuf>,
 
+	/// directory where backup files of this app are stored on macOS
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub backup_dir_macos: Option<PathBuf>,
 
+	/// directory where backup files of this app are stored on Windows
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub backup_dir_windows: Option<PathBuf>,
 
+	/// files to be backed up, must be under the dotfile root unless `dest` is set
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
-	pub files: Vec<PathBuf>,
+	pub files: Vec<FileEntry>,
 
+	/// files to be backed up only on Linux
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
-	pub files_linux: Vec<PathBuf>,
+	pub files_linux: Vec<FileEntry>,
 
+	/// files to be backed up only on macOS
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
-	pub files_macos: Vec<PathBuf>,
+	pub files_macos: Vec<FileEntry>,
 
+	/// files to be backed up only on Windows
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
-	pub files_windows: Vec<PathBuf>,
+	pub files_windows: Vec<FileEntry>,
+
+	/// what to do if a file is not found, `warn` by default
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub missing: Option<Missing>,
 
+	/// glob patterns of files to be ignored in this app
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub ignore: Vec<String>,
 
+	/// tags to select this app with `@tag`
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub tags: Vec<String>,
+
+	/// copy files as root through `privilege_helper`, e.g., for files in /etc
+	#[serde(default)]
+	#[serde(skip_serializing_if = "is_false")]
+	pub privileged: bool,
+
+	/// record owner and group of files at backup and restore them on setup
+	#[serde(default)]
+	#[serde(skip_serializing_if = "is_false")]
+	pub preserve_owner: bool,
+
+	/// modes of restored files matching glob patterns, overriding modes
+	/// recorded at backup
+	#[serde(default)]
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	pub modes: BTreeMap<String, Mode>,
+
+	/// apps to be backed up and set up before this app
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub depends_on: Vec<String>,
+
+	/// scripts to run before backup of this app
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub pre_backup: Vec<String>,
 
+	/// scripts to run after backup of this app
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub post_backup: Vec<String>,
 
+	/// scripts to run before setup of this app
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub pre_setup: Vec<String>,
 
+	/// scripts to run after setup of this app
 	#[serde(default)]
 	#[serde(skip_serializing_if = "Vec::is_empty")]
 	pub post_setup: Vec<String>,
 }
 
+/// Return a system error of copying `src` to `dest`
+fn copy_error(operation: &str, src: &Path, dest: &Path, source: io::Error) -> Error {
+	sys_error!("{} -> {}", src.display(), dest.display())
+		.with_path(dest)
+		.with_operation(operation)
+		.with_source(source)
+}
+
+/// Set mode of `path`
+fn chmod(path: &Path, mode: Mode) -> Result<()> {
+	log!("chmod {mode} {}", path.display());
+	set_mode(path, mode.0).map_err(|e| Error::io("set mode", path, e))
+}
+
+/// Copy backup `src` to `dest` through `helper`, skipping files matching
+/// `ignore`, and return the paths copied to `dest`
+fn copy_from_backup(
+	helper: &Helper,
+	src: &Path,
+	dest: &Path,
+	ignore: &[Pattern],
+	on_file: &mut dyn FnMut(&Path, Option<u64>),
+) -> Result<HashSet<PathBuf>> {
+	let mut entries = walk_local(src).map_err(|e| Error::io("read", src, e))?;
+	remove_ignored(src, &mut entries, ignore, &mut |path| on_file(path, None));
+	helper.copy(src, dest, &entries, false, None)?;
+	for entry in entries.iter().filter(|entry| !entry.is_dir) {
+		on_file(&entry.path_in(src), Some(entry.size));
+	}
+
+	Ok(entries.iter().map(|entry| entry.path_in(dest)).collect())
+}
+
+/// Apply `restored` metadata of files set up from `dest` of app `name`,
+/// through `helper` if any. Paths already matching are skipped, except for
+/// `helper`, which changes all modes and then all owners at once.
+fn restore_metadata(
+	name: &str,
+	dest: &Path,
+	restored: &[(PathBuf, Metadata)],
+	helper: Option<&Helper>,
+	reporter: &dyn Reporter,
+) -> Result<()> {
+	let mut modes = Vec::new();
+	let mut owners = Vec::new();
+	for (path, metadata) in restored {
+		// files of privileged apps may not be visible
+		let current = match helper {
+			Some(_) => None,
+			None => Metadata::of(path, true).map_err(|e| Error::io("read metadata", path, e))?,
+		};
+		if current.is_none_or(|current| current.mode != metadata.mode) {
+			modes.push((path.clone(), metadata.mode.0));
+		}
+		if let (Some(uid), Some(gid)) = (metadata.uid, metadata.gid)
+			&& current.is_none_or(|current| (current.uid, current.gid) != (Some(uid), Some(gid)))
+		{
+			owners.push((path.clone(), uid, gid));
+		}
+	}
+
+	let owned = if let Some(helper) = helper {
+		for (path, mode) in &modes {
+			log!("chmod {} {}", Mode(*mode), path.display());
+		}
+		helper.set_mode(dest, &modes)?;
+		helper.chown(dest, &owners)
+	} else {
+		for (path, mode) in &modes {
+			chmod(path, Mode(*mode))?;
+		}
+		owners.iter().try_for_each(|(path, uid, gid)| {
+			set_owner(path, *uid, *gid).map_err(|e| Error::io("change owner", path, e))
+		})
+	};
+	if let Err(e) = owned {
+		reporter.warning(
+			Some(name),
+			&format!("owner is not restored: {}", e.report()),
+		);
+	}
+
+	Ok(())
+}
+
 impl App {
-	fn merge_patterns(local: &Vec<String>, global: &Vec<String>) -> Result<Vec<Pattern>> {
+	pub(super) fn merge_patterns(
+		local: &Vec<String>,
+		global: &Vec<String>,
+	) -> Result<Vec<Pattern>> {
 		let mut ret = Vec::new();
 		for s in local {
 			ret.push(Pattern::new(s).map_err(|e| arg_error!("invalid glob pattern: {e:?}"))?);
@@ -82,6 +223,96 @@ impl App {
 		Ok(ret)
 	}
 
+	/// Return what to do if `file` is not found
+	#[must_use]
+	pub fn get_missing(&self, file: &FileEntry, config: &Config) -> Missing {
+		if file.required() || config.strict_missing {
+			Missing::Error
+		} else {
+			self.missing.unwrap_or_default()
+		}
+	}
+
+	/// Handle `path` of `file` not found in `operation` according to
+	/// `get_missing`
+	fn not_found(
+		&self,
+		operation: &str,
+		name: &str,
+		file: &FileEntry,
+		path: &Path,
+		config: &Config,
+		reporter: &dyn Reporter,
+	) -> Result<()> {
+		match self.get_missing(file, config) {
+			Missing::Error => Err(Error::new(
+				Type::App,
+				format!("file not found: {}", path.display()),
+			)
+			.with_path(path)
+			.with_operation(operation)),
+			Missing::Warn => {
+				reporter.file_skipped(name, path, "file not found");
+				Ok(())
+			}
+			Missing::Ignore => Ok(()),
+		}
+	}
+
+	/// Return patterns of `modes`, the longest first
+	fn mode_patterns(&self) -> Result<Vec<(Pattern, Mode)>> {
+		let mut ret = Vec::new();
+		for (pattern, mode) in &self.modes {
+			let pattern = expandhome(pattern).to_string_lossy().to_string();
+			let pattern =
+				Pattern::new(&pattern).map_err(|e| arg_error!("invalid glob pattern: {e:?}"))?;
+			ret.push((pattern, *mode));
+		}
+		ret.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.as_str().len()));
+
+		Ok(ret)
+	}
+
+	/// Return the helper to copy files as root if this app is privileged
+	fn helper(&self, config: &Config) -> Result<Option<Helper>> {
+		self.privileged
+			.then(|| Helper::new(config.privilege_helper.as_deref()))
+			.transpose()
+	}
+
+	/// Check that `file`, expanded to `path`, is under `dotfile_root` or has a
+	/// valid `dest`
+	pub(super) fn check_file(file: &FileEntry, path: &Path, dotfile_root: &Path) -> Result<()> {
+		if file.dest().is_none() && !path.starts_with(dotfile_root) {
+			return Err(config_error!(
+				"the file ({}) is expected to be under the dotfile root ({}) or to have a dest",
+				path.display(),
+				dotfile_root.display(),
+			));
+		}
+		if !file.is_dest_valid() {
+			return Err(config_error!(
+				"the dest of {} is expected to be a relative path in the backup directory",
+				path.display(),
+			));
+		}
+
+		Ok(())
+	}
+
+	/// Return where `file`, expanded to `path`, is stored in `backup_dir`
+	pub(super) fn backup_path(
+		file: &FileEntry,
+		path: &Path,
+		dotfile_root: &Path,
+		backup_dir: &Path,
+	) -> PathBuf {
+		backup_dir.join(
+			file.dest()
+				.unwrap_or_else(|| path.strip_prefix(dotfile_root).unwrap()),
+		)
+	}
+
 	/// Return expanded app-level `backup_dir`
 	#[must_use]
 	pub fn get_app_backup_dir(&self) -> Option<PathBuf> {
@@ -106,7 +337,7 @@ impl App {
 
 	/// Return all files to be backed up, including OS-specific files
 	#[must_use]
-	pub fn get_files(&self) -> Vec<PathBuf> {
+	pub fn get_files(&self) -> Vec<FileEntry> {
 		let mut ret = self.files.clone();
 		if cfg!(target_os = "linux") {
 			ret.extend(self.files_linux.clone());
@@ -122,116 +353,212 @@ impl App {
 	/// # Panics
 	///
 	/// Should not panic
-	pub fn backup(&self, name: &String, config: &Config) -> Result<()> {
+	pub fn backup(&self, name: &str, config: &Config, reporter: &dyn Reporter) -> Result<()> {
 		let files = self.get_files();
-		let highlight_name = format!("{GREEN}{name}{RESET}");
 		if files.is_empty() {
-			info!("Skip backup for {highlight_name}: no file configured");
+			reporter.app_skipped("backup", name, "no file configured");
 			return Ok(());
 		}
-		info!("Starting backup for {highlight_name}");
+		reporter.app_started("backup", name);
 
 		let dotfile_root = config.get_dotfile_root();
 		let backup_dir = self.get_backup_dir(config);
-		let ignore = App::merge_patterns(&self.ignore, &config.ignore)?;
-
-		for src in &files {
-			let src = expandhome(src);
-			if !src.starts_with(&dotfile_root) {
-				return Err(config_error!(
-					"the file ({}) is expected to be under the dotfile root ({})",
-					src.display(),
-					dotfile_root.display(),
-				));
-			}
-
-			if !src.exists() {
-				warn!("skip: file not found: {}", src.display());
+		let helper = self.helper(config)?;
+		let mut metadatas = Metadatas::default();
+		let mut progress = Progress::default();
+
+		for file in &files {
+			let src = expandhome(file.path());
+			Self::check_file(file, &src, &dotfile_root)?;
+			let ignore =
+				App::merge_patterns(&[file.ignore(), &self.ignore].concat(), &config.ignore)?;
+
+			// files of privileged apps may not be visible, they are found as
+			// root
+			let mut entries = match &helper {
+				Some(helper) => Some(helper.walk(&src)?),
+				None => None,
+			};
+			let exists = entries
+				.as_ref()
+				.map_or_else(|| src.exists(), |entries| !entries.is_empty());
+			if !exists {
+				self.not_found("backup", name, file, &src, config, reporter)?;
 				continue;
 			}
 
-			let dest = backup_dir.join(src.strip_prefix(&dotfile_root).unwrap());
+			let dest = Self::backup_path(file, &src, &dotfile_root, &backup_dir);
 			if let Some(dest_dir) = dest.parent()
 				&& !dest_dir.exists()
 			{
-				log!(config.verbose, "mkdir {}", dest_dir.display());
-				create_dir_all(dest_dir).map_err(|e| sys_error!("create directory error: {e}"))?;
+				log!("mkdir {}", dest_dir.display());
+				create_dir_all(dest_dir).map_err(|e| Error::io("create directory", dest_dir, e))?;
 			}
 			if config.clean && dest.exists() {
-				log!(config.verbose, "clean: remove {}", dest.display());
-				if dest.is_file() {
-					remove_file(&dest).map_err(|e| sys_error!("remove file error: {e}"))?;
+				let ret = if dest.is_file() {
+					remove_file(&dest).map_err(|e| Error::io("remove file", &dest, e))
 				} else {
-					remove_dir_all(&dest).map_err(|e| sys_error!("remove directory error: {e}"))?;
+					remove_dir_all(&dest).map_err(|e| Error::io("remove directory", &dest, e))
+				};
+				reporter.file_removed(name, &dest, &ret);
+				ret?;
+			}
+
+			let mut on_file = |path: &Path, bytes| match bytes {
+				Some(bytes) => {
+					progress.add(bytes);
+					reporter.progress(name, progress);
 				}
+				None => reporter.file_ignored(name, path),
+			};
+			let ret = if let (Some(helper), Some(entries)) = (&helper, &mut entries) {
+				remove_ignored(&src, entries, &ignore, &mut |path| on_file(path, None));
+				// the backup is owned by the owner of the backup directory
+				let owner = dest.parent().unwrap_or(&backup_dir);
+				helper
+					.copy(&src, &dest, entries, true, Some(owner))
+					.map(|()| {
+						for entry in entries.iter().filter(|entry| !entry.is_dir) {
+							on_file(&entry.path_in(&src), Some(entry.size));
+						}
+					})
+			} else if src.is_file() {
+				fs::copy(&src, &dest)
+					.map(|bytes| on_file(&src, Some(bytes)))
+					.map_err(|e| copy_error("copy file", &src, &dest, e))
+			} else {
+				copy_dir_all(&src, &dest, &ignore, &mut on_file)
+					.map_err(|e| copy_error("copy directory", &src, &dest, e))
+			};
+			reporter.file_copied(name, &src, &dest, &ret);
+			ret?;
+
+			if let Some(mode) = file.mode() {
+				chmod(&dest, mode)?;
 			}
 
-			eprintln!("  {} -> {}", src.display(), dest.display());
-			if src.is_file() {
-				fs::copy(src, dest).map_err(|e| sys_error!("copy file error: {e}"))?;
+			if let Some(entries) = &entries {
+				// files of privileged apps may not be readable, they are read
+				// as root
+				for entry in entries {
+					let metadata = Metadata {
+						mode: Mode(entry.mode),
+						uid: self.preserve_owner.then_some(entry.uid),
+						gid: self.preserve_owner.then_some(entry.gid),
+					};
+					metadatas.insert(&backup_dir, &entry.path_in(&dest), metadata);
+				}
 			} else {
-				copy_dir_all(src, dest, &ignore, config.verbose)
-					.map_err(|e| sys_error!("copy directory error: {e}"))?;
+				metadatas
+					.record(&backup_dir, &dest, &src, self.preserve_owner)
+					.map_err(|e| Error::io("read metadata", &src, e))?;
 			}
 		}
 
-		Ok(())
+		metadatas.save(&Metadatas::path(&backup_dir, name))
 	}
 
 	/// # Panics
 	///
 	/// Should not panic
-	pub fn setup(&self, name: &String, config: &Config) -> Result<()> {
+	pub fn setup(&self, name: &str, config: &Config, reporter: &dyn Reporter) -> Result<()> {
 		let files = self.get_files();
-		let highlight_name = format!("{GREEN}{name}{RESET}");
 		if files.is_empty() {
-			info!("Skip setup for {highlight_name}: no file configured");
+			reporter.app_skipped("setup", name, "no file configured");
 			return Ok(());
 		}
-		info!("Starting setup for {highlight_name}");
+		reporter.app_started("setup", name);
 
 		let dotfile_root = config.get_dotfile_root();
 		let backup_dir = self.get_backup_dir(config);
-		let ignore = App::merge_patterns(&self.ignore, &config.ignore)?;
-
-		for dest in &files {
-			let dest = expandhome(dest);
-			if !dest.starts_with(&dotfile_root) {
-				return Err(config_error!(
-					"the file ({}) is expected to be under the dotfile root ({})",
-					dest.display(),
-					dotfile_root.display(),
-				));
-			}
-
-			let src = backup_dir.join(dest.strip_prefix(&dotfile_root).unwrap());
+		let helper = self.helper(config)?;
+		let metadatas = Metadatas::load(&Metadatas::path(&backup_dir, name))?;
+		let modes = self.mode_patterns()?;
+		let mut progress = Progress::default();
+
+		for file in &files {
+			let dest = expandhome(file.path());
+			Self::check_file(file, &dest, &dotfile_root)?;
+			let ignore =
+				App::merge_patterns(&[file.ignore(), &self.ignore].concat(), &config.ignore)?;
+
+			let src = Self::backup_path(file, &dest, &dotfile_root, &backup_dir);
 			if !src.exists() {
-				warn!("skip: file not found: {}", src.display());
+				self.not_found("setup", name, file, &src, config, reporter)?;
 				continue;
 			}
 
-			if let Some(dest_dir) = dest.parent()
+			// `Helper::copy` creates the parent itself
+			if helper.is_none()
+				&& let Some(dest_dir) = dest.parent()
 				&& !dest_dir.exists()
 			{
-				log!(config.verbose, "mkdir: {}", dest_dir.display());
-				create_dir_all(dest_dir).map_err(|e| sys_error!("create directory error: {e}"))?;
+				log!("mkdir: {}", dest_dir.display());
+				create_dir_all(dest_dir).map_err(|e| Error::io("create directory", dest_dir, e))?;
 			}
-			if config.clean && dest.exists() {
-				log!(config.verbose, "clean: remove {}", dest.display());
-				if dest.is_file() {
-					remove_file(&dest).map_err(|e| sys_error!("remove file error: {e}"))?;
-				} else {
-					remove_dir_all(&dest).map_err(|e| sys_error!("remove directory error: {e}"))?;
+			if config.clean {
+				let ret = match &helper {
+					Some(helper) => helper.remove_all(&dest),
+					None if !dest.exists() => Ok(false),
+					None if dest.is_file() => remove_file(&dest)
+						.map(|()| true)
+						.map_err(|e| Error::io("remove file", &dest, e)),
+					None => remove_dir_all(&dest)
+						.map(|()| true)
+						.map_err(|e| Error::io("remove directory", &dest, e)),
+				};
+				if !matches!(ret, Ok(false)) {
+					let ret = ret.map(drop);
+					reporter.file_removed(name, &dest, &ret);
+					ret?;
 				}
 			}
 
-			eprintln!("  {} -> {}", src.display(), dest.display());
-			if src.is_file() {
-				fs::copy(src, dest).map_err(|e| sys_error!("copy file error: {e}"))?;
+			let mut on_file = |path: &Path, bytes| match bytes {
+				Some(bytes) => {
+					progress.add(bytes);
+					reporter.progress(name, progress);
+				}
+				None => reporter.file_ignored(name, path),
+			};
+			// paths copied by `helper`, which may not be visible
+			let mut copied = None;
+			let ret = if let Some(helper) = &helper {
+				copy_from_backup(helper, &src, &dest, &ignore, &mut on_file)
+					.map(|paths| copied = Some(paths))
+			} else if src.is_file() {
+				fs::copy(&src, &dest)
+					.map(|bytes| on_file(&src, Some(bytes)))
+					.map_err(|e| copy_error("copy file", &src, &dest, e))
 			} else {
-				copy_dir_all(src, dest, &ignore, config.verbose)
-					.map_err(|e| sys_error!("copy directory error: {e}"))?;
+				copy_dir_all(&src, &dest, &ignore, &mut on_file)
+					.map_err(|e| copy_error("copy directory", &src, &dest, e))
+			};
+			reporter.file_copied(name, &src, &dest, &ret);
+			ret?;
+
+			let mut restored = metadatas
+				.restore(&backup_dir, &src, &dest, &modes)
+				.map_err(|e| Error::io("read", &src, e))?;
+			restored.retain(|(target, _)| match &copied {
+				Some(copied) => copied.contains(target),
+				// ignored otherwise
+				None => target.exists(),
+			});
+			if let Some(mode) = file.mode() {
+				match restored.iter_mut().find(|(target, _)| *target == dest) {
+					Some((_, metadata)) => metadata.mode = mode,
+					None => restored.push((
+						dest.clone(),
+						Metadata {
+							mode,
+							uid: None,
+							gid: None,
+						},
+					)),
+				}
 			}
+			restore_metadata(name, &dest, &restored, helper.as_ref(), reporter)?;
 		}
 
 		Ok(())
@@ -0,0 +1,455 @@
+use super::{App, Config, FileEntry, FileOptions, Format};
+use crate::{
+	config_error, did_you_mean,
+	error::{Error, Result},
+	expandhome, field_names,
+};
+use glob::Pattern;
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf};
+use yaml_rust2::{
+	parser::{Event, MarkedEventReceiver, Parser},
+	scanner::Marker,
+};
+use yaml_serde::Value;
+
+/// A problem found in the configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+	/// key path of the problem, e.g., `apps.nvim.files[0]`
+	pub path: String,
+	/// 1-based line and column in the configuration file, if known
+	pub position: Option<(usize, usize)>,
+	pub msg: String,
+}
+
+impl Diagnostic {
+	fn new(path: String, msg: String) -> Self {
+		Self {
+			path,
+			position: None,
+			msg,
+		}
+	}
+}
+
+impl Display for Diagnostic {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if let Some((line, column)) = self.position {
+			write!(f, "{line}:{column}: ")?;
+		}
+		write!(f, "{}: {}", self.path, self.msg)
+	}
+}
+
+/// Record key paths of YAML nodes and their positions
+#[derive(Default)]
+struct Positions {
+	positions: BTreeMap<String, (usize, usize)>,
+	/// (path, current mapping key, next sequence index) of open collections
+	stack: Vec<(String, Option<String>, Option<usize>)>,
+}
+
+impl Positions {
+	/// Return the path of the node of `event`, `None` if it is a mapping key
+	fn node_path(&mut self, event: &Event, mark: Marker) -> Option<String> {
+		let position = (mark.line(), mark.col() + 1);
+		let (parent, key, index) = self.stack.last_mut()?;
+
+		if let Some(index) = index {
+			let path = format!("{parent}[{index}]");
+			*index += 1;
+			self.positions.insert(path.clone(), position);
+			Some(path)
+		} else if let Some(key) = key.take() {
+			Some(if parent.is_empty() {
+				key
+			} else {
+				format!("{parent}.{key}")
+			})
+		} else {
+			if let Event::Scalar(value, ..) = event {
+				let path = if parent.is_empty() {
+					value.clone()
+				} else {
+					format!("{parent}.{value}")
+				};
+				self.positions.insert(path, position);
+				*key = Some(value.clone());
+			}
+			None
+		}
+	}
+}
+
+impl MarkedEventReceiver for Positions {
+	fn on_event(&mut self, event: Event, mark: Marker) {
+		match event {
+			Event::MappingStart(..) | Event::SequenceStart(..) => {
+				let path = if self.stack.is_empty() {
+					Some(String::new())
+				} else {
+					self.node_path(&event, mark)
+				};
+				let index = matches!(event, Event::SequenceStart(..)).then_some(0);
+				self.stack.push((path.unwrap_or_default(), None, index));
+			}
+			Event::MappingEnd | Event::SequenceEnd => {
+				self.stack.pop();
+			}
+			Event::Scalar(..) | Event::Alias(..) => {
+				self.node_path(&event, mark);
+			}
+			_ => (),
+		}
+	}
+}
+
+/// Return positions of key paths in YAML `source`, or an empty map if it is
+/// not valid YAML. Since JSON is YAML, this also works for JSON.
+fn positions(source: &str) -> BTreeMap<String, (usize, usize)> {
+	let mut positions = Positions::default();
+	match Parser::new_from_str(source).load(&mut positions, false) {
+		Ok(()) => positions.positions,
+		Err(_) => BTreeMap::new(),
+	}
+}
+
+/// Return unknown keys in configuration `value` of `Config` and `App`
+fn unknown_keys(value: &Value) -> Vec<Diagnostic> {
+	let mut ret = Vec::new();
+	let check = |ret: &mut Vec<Diagnostic>, value: &Value, parent: &str, fields: &[&str]| {
+		let Some(mapping) = value.as_mapping() else {
+			return;
+		};
+		for key in mapping.keys() {
+			let Some(key) = key.as_str() else {
+				continue;
+			};
+			if !fields.contains(&key) {
+				let path = if parent.is_empty() {
+					key.to_string()
+				} else {
+					format!("{parent}.{key}")
+				};
+				ret.push(Diagnostic::new(
+					path,
+					format!("unknown key{}", did_you_mean(key, fields.iter().copied())),
+				));
+			}
+		}
+	};
+
+	check(&mut ret, value, "", field_names::<Config>());
+	if let Some(apps) = value.get("apps").and_then(Value::as_mapping) {
+		for (name, app) in apps {
+			let Some(name) = name.as_str() else {
+				continue;
+			};
+			check(&mut ret, app, &format!("apps.{name}"), field_names::<App>());
+			for key in ["files", "files_linux", "files_macos", "files_windows"] {
+				let Some(files) = app.get(key).and_then(Value::as_sequence) else {
+					continue;
+				};
+				for (i, file) in files.iter().enumerate() {
+					let parent = format!("apps.{name}.{key}[{i}]");
+					check(&mut ret, file, &parent, field_names::<FileOptions>());
+				}
+			}
+		}
+	}
+
+	ret
+}
+
+/// Set positions of `diagnostics` in YAML `source` and sort them by position
+fn locate(source: &str, diagnostics: &mut [Diagnostic]) {
+	let positions = positions(source);
+	for diagnostic in diagnostics.iter_mut() {
+		diagnostic.position = positions.get(&diagnostic.path).copied();
+	}
+	diagnostics.sort_by_key(|diagnostic| diagnostic.position);
+}
+
+/// Return an error listing all unknown keys in `source`
+pub(crate) fn check_unknown_keys(source: &str, format: Format) -> Result<()> {
+	let value: Value = format.parse(source)?;
+	let mut diagnostics = unknown_keys(&value);
+	locate(source, &mut diagnostics);
+
+	Error::combine(
+		diagnostics
+			.iter()
+			.map(|diagnostic| config_error!("{diagnostic}"))
+			.collect(),
+	)
+}
+
+/// A file entry of an app
+struct Entry<'a> {
+	app: &'a str,
+	/// key path of the entry
+	path: String,
+	/// OS the entry applies to, `None` means all
+	os: Option<&'a str>,
+	options: &'a FileEntry,
+	/// expanded path of the entry
+	file: PathBuf,
+}
+
+impl Entry<'_> {
+	/// Whether both entries may apply on the same system
+	fn coexists(&self, other: &Self) -> bool {
+		self.os.is_none() || other.os.is_none() || self.os == other.os
+	}
+}
+
+impl Config {
+	/// Check configuration `source` in `format` for problems without running
+	/// anything.
+	///
+	/// Return an error if `source` cannot be parsed at all. Positions are not
+	/// available for TOML.
+	pub fn check(source: &str, format: Format) -> Result<Vec<Diagnostic>> {
+		// not `parse_as`, which stops at the first unknown recipe or cycle
+		let mut config: Self = format.parse(source)?;
+		let value: Value = format.parse(source)?;
+
+		let mut ret = unknown_keys(&value);
+		ret.extend(config.check_recipes());
+		ret.extend(config.check_dependencies());
+		ret.extend(config.check_globs());
+		ret.extend(config.check_files());
+		ret.extend(config.check_backup_dirs());
+
+		locate(source, &mut ret);
+
+		Ok(ret)
+	}
+
+	fn entries(&self) -> Vec<Entry<'_>> {
+		let mut ret = Vec::new();
+		for (name, app) in &self.apps {
+			let lists = [
+				("files", None, &app.files),
+				("files_linux", Some("linux"), &app.files_linux),
+				("files_macos", Some("macos"), &app.files_macos),
+				("files_windows", Some("windows"), &app.files_windows),
+			];
+			for (key, os, files) in lists {
+				for (i, file) in files.iter().enumerate() {
+					ret.push(Entry {
+						app: name,
+						path: format!("apps.{name}.{key}[{i}]"),
+						os,
+						options: file,
+						file: expandhome(file.path()),
+					});
+				}
+			}
+		}
+
+		ret
+	}
+
+	/// Apply recipes of all apps, reporting unknown ones
+	fn check_recipes(&mut self) -> Vec<Diagnostic> {
+		let mut ret = Vec::new();
+		for (name, app) in &mut self.apps {
+			if let Err(e) = app.apply_recipe() {
+				ret.push(Diagnostic::new(format!("apps.{name}.recipe"), e.msg));
+			}
+		}
+
+		ret
+	}
+
+	fn check_dependencies(&self) -> Vec<Diagnostic> {
+		let mut ret = Vec::new();
+		let mut known = self.clone();
+		for (name, app) in &mut known.apps {
+			app.depends_on.retain(|dep| {
+				let found = self.apps.contains_key(dep);
+				if !found {
+					let i = self.apps[name]
+						.depends_on
+						.iter()
+						.position(|other| other == dep)
+						.unwrap_or_default();
+					ret.push(Diagnostic::new(
+						format!("apps.{name}.depends_on[{i}]"),
+						format!(
+							"unknown app {dep}{}",
+							did_you_mean(dep, self.apps.keys().map(String::as_str))
+						),
+					));
+				}
+				found
+			});
+		}
+
+		let names: Vec<String> = known.apps.keys().cloned().collect();
+		if let Ok((_, Some(cycle))) = known.sort_dependencies(&names) {
+			ret.push(Diagnostic::new(
+				format!("apps.{}.depends_on", cycle[0]),
+				format!("dependency cycle among apps: {}", cycle.join(" -> ")),
+			));
+		}
+
+		ret
+	}
+
+	fn check_globs(&self) -> Vec<Diagnostic> {
+		let mut ret = Vec::new();
+		let mut check = |path: String, patterns: &[String]| {
+			for (i, pattern) in patterns.iter().enumerate() {
+				if let Err(e) = Pattern::new(pattern) {
+					ret.push(Diagnostic::new(
+						format!("{path}[{i}]"),
+						format!("invalid glob pattern: {e}"),
+					));
+				}
+			}
+		};
+
+		check("ignore".to_string(), &self.ignore);
+		for (name, app) in &self.apps {
+			check(format!("apps.{name}.ignore"), &app.ignore);
+		}
+		for entry in self.entries() {
+			check(format!("{}.ignore", entry.path), entry.options.ignore());
+		}
+		for (name, app) in &self.apps {
+			for pattern in app.modes.keys() {
+				if let Err(e) = Pattern::new(pattern) {
+					ret.push(Diagnostic::new(
+						format!("apps.{name}.modes.{pattern}"),
+						format!("invalid glob pattern: {e}"),
+					));
+				}
+			}
+		}
+
+		ret
+	}
+
+	fn check_files(&self) -> Vec<Diagnostic> {
+		let mut ret = Vec::new();
+		let dotfile_root = self.get_dotfile_root();
+		let entries = self.entries();
+
+		for (name, app) in &self.apps {
+			let has_hooks = !(app.pre_backup.is_empty()
+				&& app.post_backup.is_empty()
+				&& app.pre_setup.is_empty()
+				&& app.post_setup.is_empty());
+			if !has_hooks && !entries.iter().any(|entry| entry.app == name) {
+				ret.push(Diagnostic::new(
+					format!("apps.{name}"),
+					"app has no files and no hooks".to_string(),
+				));
+			}
+		}
+
+		for (i, entry) in entries.iter().enumerate() {
+			if !entry.options.is_dest_valid() {
+				ret.push(Diagnostic::new(
+					format!("{}.dest", entry.path),
+					"dest is expected to be a relative path in the backup directory".to_string(),
+				));
+			}
+			if entry.options.dest().is_none() && !entry.file.starts_with(&dotfile_root) {
+				ret.push(Diagnostic::new(
+					entry.path.clone(),
+					format!(
+						"{} is outside of the dotfile root ({})",
+						entry.file.display(),
+						dotfile_root.display()
+					),
+				));
+			}
+
+			for other in &entries[..i] {
+				if !entry.coexists(other) {
+					continue;
+				}
+				let msg = if entry.file == other.file {
+					if entry.app == other.app {
+						continue;
+					}
+					format!(
+						"{} is also claimed by app {} ({})",
+						entry.file.display(),
+						other.app,
+						other.path
+					)
+				} else if entry.file.starts_with(&other.file) {
+					format!("{} is inside {}", entry.file.display(), other.path)
+				} else if other.file.starts_with(&entry.file) {
+					format!("{} contains {}", entry.file.display(), other.path)
+				} else {
+					continue;
+				};
+				ret.push(Diagnostic::new(entry.path.clone(), msg));
+			}
+		}
+
+		ret
+	}
+
+	fn check_backup_dirs(&self) -> Vec<Diagnostic> {
+		let mut dirs = vec![
+			("backup_dir".to_string(), Some(&self.backup_dir)),
+			(
+				"backup_dir_linux".to_string(),
+				self.backup_dir_linux.as_ref(),
+			),
+			(
+				"backup_dir_macos".to_string(),
+				self.backup_dir_macos.as_ref(),
+			),
+			(
+				"backup_dir_windows".to_string(),
+				self.backup_dir_windows.as_ref(),
+			),
+		];
+		for (name, app) in &self.apps {
+			dirs.extend([
+				(format!("apps.{name}.backup_dir"), app.backup_dir.as_ref()),
+				(
+					format!("apps.{name}.backup_dir_linux"),
+					app.backup_dir_linux.as_ref(),
+				),
+				(
+					format!("apps.{name}.backup_dir_macos"),
+					app.backup_dir_macos.as_ref(),
+				),
+				(
+					format!("apps.{name}.backup_dir_windows"),
+					app.backup_dir_windows.as_ref(),
+				),
+			]);
+		}
+
+		let entries = self.entries();
+		let mut ret = Vec::new();
+		for (path, dir) in dirs {
+			let Some(dir) = dir.map(expandhome) else {
+				continue;
+			};
+			for entry in &entries {
+				if dir.starts_with(&entry.file) {
+					ret.push(Diagnostic::new(
+						path.clone(),
+						format!(
+							"backup directory {} is inside {}",
+							dir.display(),
+							entry.path
+						),
+					));
+				}
+			}
+		}
+
+		ret
+	}
+}
@@ -0,0 +1,249 @@
+use super::is_false;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use serde_json::{Value, json};
+use std::{
+	borrow::Cow,
+	fmt::Display,
+	path::{Component, Path, PathBuf},
+};
+
+/// What to do if a file of an app is not found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Missing {
+	/// fail the app
+	Error,
+	/// warn and skip the file
+	#[default]
+	Warn,
+	/// skip the file silently
+	Ignore,
+}
+
+/// Permission bits of a file, written in octal, e.g., `"0600"` or `600`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode(pub u32);
+
+impl Mode {
+	/// Parse octal digits `s`, with optional `0o` prefix
+	fn parse(s: &str) -> Option<Self> {
+		let digits = s.strip_prefix("0o").unwrap_or(s);
+		u32::from_str_radix(digits, 8)
+			.ok()
+			.filter(|&mode| mode <= 0o7777)
+			.map(Self)
+	}
+}
+
+impl Display for Mode {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{:04o}", self.0)
+	}
+}
+
+impl JsonSchema for Mode {
+	fn schema_name() -> Cow<'static, str> {
+		"Mode".into()
+	}
+
+	/// A string or, as YAML allows plain scalars like `600`, an integer, both
+	/// of octal digits
+	fn json_schema(_: &mut SchemaGenerator) -> Schema {
+		json_schema!({
+			"type": ["string", "integer"],
+			"pattern": "^(0o)?[0-7]{1,4}$",
+			"minimum": 0,
+			"maximum": 7777
+		})
+	}
+}
+
+impl Serialize for Mode {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.to_string())
+	}
+}
+
+impl<'de> Deserialize<'de> for Mode {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct ModeVisitor;
+
+		impl de::Visitor<'_> for ModeVisitor {
+			type Value = Mode;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "an octal string like \"0600\"")
+			}
+
+			fn visit_str<E: de::Error>(self, v: &str) -> Result<Mode, E> {
+				Mode::parse(v).ok_or_else(|| {
+					E::custom(format!("invalid mode {v:?}, expected octal like \"0600\""))
+				})
+			}
+
+			// a number has lost how it was written, `0o644` and `420` are the
+			// same, so it cannot be taken as octal
+			fn visit_u64<E: de::Error>(self, v: u64) -> Result<Mode, E> {
+				Err(E::custom(format!(
+					"invalid mode {v}, write it as a string like \"0600\""
+				)))
+			}
+
+			fn visit_i64<E: de::Error>(self, v: i64) -> Result<Mode, E> {
+				Err(E::custom(format!(
+					"invalid mode {v}, write it as a string like \"0600\""
+				)))
+			}
+		}
+
+		// YAML gives plain scalars like `600` as they are written
+		deserializer.deserialize_str(ModeVisitor)
+	}
+}
+
+/// A file with options
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[schemars(transform = path_or_src)]
+pub struct FileOptions {
+	/// path of the file, which may be outside of the dotfile root if `dest` is
+	/// set
+	#[serde(alias = "src")]
+	pub path: PathBuf,
+
+	/// path in the backup directory, instead of the path relative to the
+	/// dotfile root, required for files outside of the dotfile root
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub dest: Option<PathBuf>,
+
+	/// permission bits of the copied file on backup and setup
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub mode: Option<Mode>,
+
+	/// fail if the file is not found, regardless of `missing`
+	#[serde(default)]
+	#[serde(skip_serializing_if = "is_false")]
+	pub required: bool,
+
+	/// ignored file patterns only for this file
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub ignore: Vec<String>,
+}
+
+/// Make the schema of `FileOptions` accept `src` instead of `path`, like its
+/// alias
+fn path_or_src(schema: &mut Schema) {
+	if let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut)
+		&& let Some(path) = properties.get("path").cloned()
+	{
+		properties.insert("src".to_string(), path);
+	}
+	schema.remove("required");
+	schema.insert(
+		"oneOf".to_string(),
+		json!([{ "required": ["path"] }, { "required": ["src"] }]),
+	);
+}
+
+/// A file of an app, either a path or a path with options
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum FileEntry {
+	Path(PathBuf),
+	Options(FileOptions),
+}
+
+impl<'de> Deserialize<'de> for FileEntry {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		// not untagged, so that errors in options are not replaced by a generic one
+		struct EntryVisitor;
+
+		impl<'de> de::Visitor<'de> for EntryVisitor {
+			type Value = FileEntry;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "a path or a map of file options")
+			}
+
+			fn visit_str<E: de::Error>(self, v: &str) -> Result<FileEntry, E> {
+				Ok(FileEntry::Path(v.into()))
+			}
+
+			fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<FileEntry, A::Error> {
+				FileOptions::deserialize(de::value::MapAccessDeserializer::new(map))
+					.map(FileEntry::Options)
+			}
+		}
+
+		deserializer.deserialize_any(EntryVisitor)
+	}
+}
+
+impl FileEntry {
+	/// Return the path of the file, not expanded
+	#[must_use]
+	pub fn path(&self) -> &PathBuf {
+		match self {
+			Self::Path(path) => path,
+			Self::Options(options) => &options.path,
+		}
+	}
+
+	#[must_use]
+	pub fn dest(&self) -> Option<&Path> {
+		match self {
+			Self::Path(_) => None,
+			Self::Options(options) => options.dest.as_deref(),
+		}
+	}
+
+	#[must_use]
+	pub fn mode(&self) -> Option<Mode> {
+		match self {
+			Self::Path(_) => None,
+			Self::Options(options) => options.mode,
+		}
+	}
+
+	#[must_use]
+	pub fn required(&self) -> bool {
+		match self {
+			Self::Path(_) => false,
+			Self::Options(options) => options.required,
+		}
+	}
+
+	#[must_use]
+	pub fn ignore(&self) -> &[String] {
+		match self {
+			Self::Path(_) => &[],
+			Self::Options(options) => &options.ignore,
+		}
+	}
+
+	/// Whether `dest` is a relative path staying inside the backup directory
+	#[must_use]
+	pub fn is_dest_valid(&self) -> bool {
+		self.dest().is_none_or(|dest| {
+			dest.components().next().is_some()
+				&& dest
+					.components()
+					.all(|component| matches!(component, Component::Normal(_)))
+		})
+	}
+}
+
+impl From<PathBuf> for FileEntry {
+	fn from(path: PathBuf) -> Self {
+		Self::Path(path)
+	}
+}
+
+impl From<&str> for FileEntry {
+	fn from(path: &str) -> Self {
+		Self::Path(path.into())
+	}
+}
@@ -0,0 +1,77 @@
+use crate::{arg_error, config_error, error::Result};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{fmt::Display, path::Path, str::FromStr};
+
+/// Configuration file format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+	#[default]
+	Yaml,
+	Toml,
+	Json,
+}
+
+impl Format {
+	/// All formats, in the order of searching configuration files
+	pub const ALL: [Format; 3] = [Format::Yaml, Format::Toml, Format::Json];
+
+	/// File extension of the format
+	#[must_use]
+	pub fn extension(self) -> &'static str {
+		match self {
+			Self::Yaml => "yml",
+			Self::Toml => "toml",
+			Self::Json => "json",
+		}
+	}
+
+	/// Decide the format by the extension of `path`, default is YAML
+	#[must_use]
+	pub fn from_path(path: &Path) -> Self {
+		path.extension()
+			.and_then(|ext| ext.to_str())
+			.and_then(|ext| ext.parse().ok())
+			.unwrap_or_default()
+	}
+
+	pub fn parse<T: DeserializeOwned>(self, source: &str) -> Result<T> {
+		match self {
+			Self::Yaml => yaml_serde::from_str(source).map_err(|e| config_error!("{e}")),
+			Self::Toml => toml::from_str(source).map_err(|e| config_error!("{e}")),
+			Self::Json => serde_json::from_str(source).map_err(|e| config_error!("{e}")),
+		}
+	}
+
+	pub fn serialize<T: Serialize>(self, value: &T) -> Result<String> {
+		match self {
+			Self::Yaml => yaml_serde::to_string(value).map_err(|e| config_error!("{e}")),
+			Self::Toml => toml::to_string(value).map_err(|e| config_error!("{e}")),
+			Self::Json => serde_json::to_string_pretty(value)
+				.map(|json| json + "\n")
+				.map_err(|e| config_error!("{e}")),
+		}
+	}
+}
+
+impl FromStr for Format {
+	type Err = crate::error::Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match s {
+			"yml" | "yaml" => Ok(Self::Yaml),
+			"toml" => Ok(Self::Toml),
+			"json" => Ok(Self::Json),
+			_ => Err(arg_error!("unknown format: {s:?}")),
+		}
+	}
+}
+
+impl Display for Format {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Yaml => write!(f, "YAML"),
+			Self::Toml => write!(f, "TOML"),
+			Self::Json => write!(f, "JSON"),
+		}
+	}
+}
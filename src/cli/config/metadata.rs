@@ -0,0 +1,179 @@
+use super::Mode;
+use crate::{
+	config_error,
+	error::{Error, Result},
+};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::BTreeMap,
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+/// Directory in the backup directory where metadata of apps is stored
+pub const METADATA_DIR: &str = ".dotbackup";
+
+/// Mode and owner of a file recorded at backup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+	pub mode: Mode,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub uid: Option<u32>,
+	#[serde(default)]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub gid: Option<u32>,
+}
+
+impl Metadata {
+	/// Read metadata of `path`, with its owner if `owner`. Return `None` on
+	/// non-Unix systems.
+	pub fn of(path: &Path, owner: bool) -> io::Result<Option<Self>> {
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::MetadataExt;
+			let metadata = fs::metadata(path)?;
+			Ok(Some(Self {
+				mode: Mode(metadata.mode() & 0o7777),
+				uid: owner.then(|| metadata.uid()),
+				gid: owner.then(|| metadata.gid()),
+			}))
+		}
+		#[cfg(not(unix))]
+		{
+			let _ = (path, owner);
+			Ok(None)
+		}
+	}
+}
+
+/// Metadata of files of an app, by paths relative to the backup directory
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Metadatas(pub BTreeMap<PathBuf, Metadata>);
+
+impl Metadatas {
+	/// Return the path of metadata of `app` in `backup_dir`
+	#[must_use]
+	pub fn path(backup_dir: &Path, app: &str) -> PathBuf {
+		backup_dir.join(METADATA_DIR).join(format!("{app}.json"))
+	}
+
+	/// Load metadata from `path`, empty if it does not exist
+	pub fn load(path: &Path) -> Result<Self> {
+		if !path.exists() {
+			return Ok(Self::default());
+		}
+		let content = fs::read_to_string(path).map_err(|e| Error::io("read", path, e))?;
+		serde_json::from_str(&content).map_err(|e| config_error!("{e}").with_path(path))
+	}
+
+	pub fn save(&self, path: &Path) -> Result<()> {
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir).map_err(|e| Error::io("create directory", dir, e))?;
+		}
+		let content =
+			serde_json::to_string_pretty(self).map_err(|e| config_error!("{e}").with_path(path))?;
+		fs::write(path, content + "\n").map_err(|e| Error::io("write", path, e))
+	}
+
+	/// Record metadata of `dest` in `backup_dir` and everything in it, read
+	/// from the same paths under `src`. Old files in `dest` which are not in
+	/// `src` are skipped.
+	pub fn record(
+		&mut self,
+		backup_dir: &Path,
+		dest: &Path,
+		src: &Path,
+		owner: bool,
+	) -> io::Result<()> {
+		for path in walk(dest)? {
+			let from = rebase(&path, dest, src);
+			let metadata = match Metadata::of(&from, owner) {
+				Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+				ret => ret?,
+			};
+			if let Some(metadata) = metadata {
+				self.insert(backup_dir, &path, metadata);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Record `metadata` of `path` in `backup_dir`
+	pub fn insert(&mut self, backup_dir: &Path, path: &Path, metadata: Metadata) {
+		let key = path.strip_prefix(backup_dir).unwrap_or(path);
+		self.0.insert(key.to_path_buf(), metadata);
+	}
+
+	/// Return metadata of `path` in `backup_dir`
+	#[must_use]
+	pub fn get(&self, backup_dir: &Path, path: &Path) -> Option<Metadata> {
+		self.0
+			.get(path.strip_prefix(backup_dir).unwrap_or(path))
+			.copied()
+	}
+
+	/// Return metadata to restore for `dest` set up from `src` in `backup_dir`
+	/// and everything in it, children before their parents, in case a
+	/// directory becomes read-only. Modes of the first of `modes` matching a
+	/// path override recorded modes.
+	pub fn restore(
+		&self,
+		backup_dir: &Path,
+		src: &Path,
+		dest: &Path,
+		modes: &[(Pattern, Mode)],
+	) -> io::Result<Vec<(PathBuf, Metadata)>> {
+		let mut ret = Vec::new();
+		for path in walk(src)?.iter().rev() {
+			let target = rebase(path, src, dest);
+			let recorded = self.get(backup_dir, path);
+			let forced = modes
+				.iter()
+				.find(|(pattern, _)| pattern.matches_path(&target))
+				.map(|(_, mode)| *mode);
+			let metadata = match (recorded, forced) {
+				(Some(recorded), Some(mode)) => Metadata { mode, ..recorded },
+				(Some(recorded), None) => recorded,
+				(None, Some(mode)) => Metadata {
+					mode,
+					uid: None,
+					gid: None,
+				},
+				(None, None) => continue,
+			};
+			ret.push((target, metadata));
+		}
+
+		Ok(ret)
+	}
+}
+
+/// Return `path` in `from` moved to the same place in `to`
+#[must_use]
+pub fn rebase(path: &Path, from: &Path, to: &Path) -> PathBuf {
+	match path.strip_prefix(from) {
+		Ok(relative) if !relative.as_os_str().is_empty() => to.join(relative),
+		_ => to.to_path_buf(),
+	}
+}
+
+/// Return `path` and everything in it, parents before their children
+pub fn walk(path: &Path) -> io::Result<Vec<PathBuf>> {
+	let mut ret = vec![path.to_path_buf()];
+	if fs::metadata(path)?.is_dir() {
+		let mut entries = path
+			.read_dir()?
+			.map(|entry| entry.map(|entry| entry.path()))
+			.collect::<io::Result<Vec<_>>>()?;
+		entries.sort();
+		for entry in entries {
+			ret.extend(walk(&entry)?);
+		}
+	}
+
+	Ok(ret)
+}
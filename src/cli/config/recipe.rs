@@ -0,0 +1,102 @@
+use super::{App, Config};
+use crate::{
+	config_error, did_you_mean,
+	error::{Error, Result},
+};
+use std::{collections::BTreeMap, sync::LazyLock};
+
+static RECIPES: LazyLock<BTreeMap<String, App>> = LazyLock::new(|| {
+	yaml_serde::from_str(include_str!("recipes.yml")).expect("built-in recipes are invalid")
+});
+
+/// Return built-in recipes of well-known apps
+///
+/// # Panics
+///
+/// Should not panic, built-in recipes are tested
+#[must_use]
+pub fn recipes() -> &'static BTreeMap<String, App> {
+	&RECIPES
+}
+
+/// Return `recipe` followed by items of `user` not in it
+fn combine(recipe: &[String], user: &[String]) -> Vec<String> {
+	let mut ret = recipe.to_vec();
+	ret.extend(user.iter().filter(|item| !recipe.contains(item)).cloned());
+	ret
+}
+
+impl App {
+	/// Merge the recipe of this app into it.
+	///
+	/// Fields set in this app take priority. File lists of the recipe are used
+	/// only if this app has none, `ignore` and `tags` are combined.
+	pub fn apply_recipe(&mut self) -> Result<()> {
+		let Some(name) = &self.recipe else {
+			return Ok(());
+		};
+		let recipe = recipes().get(name).ok_or_else(|| {
+			config_error!(
+				"unknown recipe: {name}{}",
+				did_you_mean(name, recipes().keys().map(String::as_str))
+			)
+		})?;
+
+		let has_files = !(self.files.is_empty()
+			&& self.files_linux.is_empty()
+			&& self.files_macos.is_empty()
+			&& self.files_windows.is_empty());
+		if !has_files {
+			self.files.clone_from(&recipe.files);
+			self.files_linux.clone_from(&recipe.files_linux);
+			self.files_macos.clone_from(&recipe.files_macos);
+			self.files_windows.clone_from(&recipe.files_windows);
+		}
+
+		let options = [
+			(&mut self.backup_dir, &recipe.backup_dir),
+			(&mut self.backup_dir_linux, &recipe.backup_dir_linux),
+			(&mut self.backup_dir_macos, &recipe.backup_dir_macos),
+			(&mut self.backup_dir_windows, &recipe.backup_dir_windows),
+		];
+		for (user, recipe) in options {
+			if user.is_none() {
+				user.clone_from(recipe);
+			}
+		}
+
+		let lists = [
+			(&mut self.depends_on, &recipe.depends_on),
+			(&mut self.pre_backup, &recipe.pre_backup),
+			(&mut self.post_backup, &recipe.post_backup),
+			(&mut self.pre_setup, &recipe.pre_setup),
+			(&mut self.post_setup, &recipe.post_setup),
+		];
+		for (user, recipe) in lists {
+			if user.is_empty() {
+				user.clone_from(recipe);
+			}
+		}
+
+		self.ignore = combine(&recipe.ignore, &self.ignore);
+		self.tags = combine(&recipe.tags, &self.tags);
+
+		Ok(())
+	}
+}
+
+impl Config {
+	/// Apply recipes of all apps
+	pub fn apply_recipes(&mut self) -> Result<()> {
+		Error::combine(
+			self.apps
+				.iter_mut()
+				.filter_map(|(name, app)| {
+					app.apply_recipe()
+						.err()
+						.map(|e| config_error!("app {name}: {}", e.msg))
+				})
+				.collect(),
+		)
+	}
+}
@@ -0,0 +1,286 @@
+//! Comparison of files of apps with their backups, for `status` and `diff`.
+
+use super::{Action, App, Config};
+use crate::{
+	error::{Error, Result},
+	expandhome,
+};
+use glob::Pattern;
+use serde::Serialize;
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fmt::Display,
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+/// State of a file compared with its backup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+	/// the file and its backup have the same content
+	Same,
+	/// the file and its backup differ
+	Modified,
+	/// the file is not backed up yet
+	NotBackedUp,
+	/// only the backup exists
+	OnlyInBackup,
+}
+
+impl Display for State {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Same => write!(f, "same"),
+			Self::Modified => write!(f, "modified"),
+			Self::NotBackedUp => write!(f, "not backed up"),
+			Self::OnlyInBackup => write!(f, "only in backup"),
+		}
+	}
+}
+
+/// A file compared with its backup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileStatus {
+	/// path of the file, expanded
+	pub path: PathBuf,
+	/// path of the backup
+	pub backup: PathBuf,
+	pub state: State,
+}
+
+/// Files of an app compared with their backups
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AppStatus {
+	pub name: String,
+	pub files: Vec<FileStatus>,
+}
+
+impl AppStatus {
+	/// Return files which differ from their backups
+	pub fn differences(&self) -> impl Iterator<Item = &FileStatus> {
+		self.files.iter().filter(|file| file.state != State::Same)
+	}
+}
+
+/// What a backup or setup does to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+	Create,
+	Overwrite,
+	/// only with `clean`
+	Delete,
+}
+
+impl Display for ChangeKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Create => write!(f, "create"),
+			Self::Overwrite => write!(f, "overwrite"),
+			Self::Delete => write!(f, "delete"),
+		}
+	}
+}
+
+/// A change of a file by a backup or setup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+	pub kind: ChangeKind,
+	/// path of the file changed, which is the backup for backup
+	pub path: PathBuf,
+}
+
+/// Changes of an app by a backup or setup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AppChanges {
+	pub name: String,
+	pub changes: Vec<Change>,
+}
+
+/// Whether each path in a file tree is a directory, by paths relative to its
+/// root, which is the empty path
+type Tree = BTreeMap<PathBuf, bool>;
+
+/// Return the tree of `path`, empty if it does not exist. Files whose names
+/// match `ignore` are left out, like `copy_dir_all` does.
+fn tree(path: &Path, ignore: &[Pattern]) -> io::Result<Tree> {
+	let mut ret = Tree::new();
+	let metadata = match fs::metadata(path) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ret),
+		metadata => metadata?,
+	};
+	ret.insert(PathBuf::new(), metadata.is_dir());
+	if metadata.is_dir() {
+		walk_tree(path, Path::new(""), ignore, &mut ret)?;
+	}
+
+	Ok(ret)
+}
+
+fn walk_tree(root: &Path, relative: &Path, ignore: &[Pattern], ret: &mut Tree) -> io::Result<()> {
+	for entry in root.join(relative).read_dir()? {
+		let entry = entry?;
+		let name = PathBuf::from(entry.file_name());
+		if ignore.iter().any(|pattern| pattern.matches_path(&name)) {
+			continue;
+		}
+
+		let relative = relative.join(name);
+		let is_dir = entry.file_type()?.is_dir();
+		ret.insert(relative.clone(), is_dir);
+		if is_dir {
+			walk_tree(root, &relative, ignore, ret)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Return `relative` in `root`, which is `root` itself if `relative` is empty
+fn join(root: &Path, relative: &Path) -> PathBuf {
+	if relative.as_os_str().is_empty() {
+		root.to_path_buf()
+	} else {
+		root.join(relative)
+	}
+}
+
+/// Whether files `a` and `b` have the same content
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+	if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+		return Ok(false);
+	}
+
+	Ok(fs::read(a)? == fs::read(b)?)
+}
+
+/// Compare file or directory `path` with its backup `backup`, ignoring files
+/// matching `ignore` in `path` and those matching `ignore_backup` in `backup`.
+/// Directories are not compared themselves, but files in them are.
+fn compare(
+	path: &Path,
+	backup: &Path,
+	ignore: &[Pattern],
+	ignore_backup: &[Pattern],
+) -> Result<Vec<FileStatus>> {
+	let local = tree(path, ignore).map_err(|e| Error::io("read", path, e))?;
+	let backed_up = tree(backup, ignore_backup).map_err(|e| Error::io("read", backup, e))?;
+
+	let mut ret = Vec::new();
+	for relative in local
+		.keys()
+		.chain(backed_up.keys())
+		.collect::<BTreeSet<_>>()
+	{
+		let (file, backup_file) = (join(path, relative), join(backup, relative));
+		let state = match (local.get(relative), backed_up.get(relative)) {
+			(Some(false), Some(false)) => {
+				if same_content(&file, &backup_file).map_err(|e| Error::io("read", &file, e))? {
+					State::Same
+				} else {
+					State::Modified
+				}
+			}
+			(Some(false), None) => State::NotBackedUp,
+			(None, Some(false)) => State::OnlyInBackup,
+			(Some(is_dir), Some(backup_is_dir)) if is_dir != backup_is_dir => State::Modified,
+			_ => continue,
+		};
+		ret.push(FileStatus {
+			path: file,
+			backup: backup_file,
+			state,
+		});
+	}
+
+	Ok(ret)
+}
+
+impl App {
+	/// Compare files of this app with their backups. Files not found on either
+	/// side are left out. If `cleaned` is `Some(true)` (`Some(false)`), ignored
+	/// files are still compared in backups (original files), because `clean`
+	/// removes them.
+	fn compare(&self, config: &Config, cleaned: Option<bool>) -> Result<Vec<FileStatus>> {
+		let dotfile_root = config.get_dotfile_root();
+		let backup_dir = self.get_backup_dir(config);
+
+		let mut ret = Vec::new();
+		for file in &self.get_files() {
+			let path = expandhome(file.path());
+			Self::check_file(file, &path, &dotfile_root)?;
+			let ignore =
+				App::merge_patterns(&[file.ignore(), &self.ignore].concat(), &config.ignore)?;
+			let (ignore, ignore_backup) = match cleaned {
+				Some(true) => (ignore, Vec::new()),
+				Some(false) => (Vec::new(), ignore),
+				None => (ignore.clone(), ignore),
+			};
+
+			let backup = Self::backup_path(file, &path, &dotfile_root, &backup_dir);
+			ret.extend(compare(&path, &backup, &ignore, &ignore_backup)?);
+		}
+
+		Ok(ret)
+	}
+
+	/// Compare files of this app with their backups
+	pub fn status(&self, config: &Config) -> Result<Vec<FileStatus>> {
+		self.compare(config, None)
+	}
+
+	/// Return changes of files by `action` (backup or setup) of this app
+	pub fn diff(&self, action: &Action, config: &Config) -> Result<Vec<Change>> {
+		let setup = matches!(action, Action::Setup);
+		let cleaned = config.clean.then_some(!setup);
+
+		Ok(self
+			.compare(config, cleaned)?
+			.into_iter()
+			.filter_map(|file| {
+				let kind = match (file.state, setup) {
+					(State::Modified, _) => ChangeKind::Overwrite,
+					(State::NotBackedUp, false) | (State::OnlyInBackup, true) => ChangeKind::Create,
+					(State::NotBackedUp, true) | (State::OnlyInBackup, false) if config.clean => {
+						ChangeKind::Delete
+					}
+					_ => return None,
+				};
+				Some(Change {
+					kind,
+					path: if setup { file.path } else { file.backup },
+				})
+			})
+			.collect())
+	}
+}
+
+impl Config {
+	/// Compare files of selected apps with their backups
+	pub fn status(&self) -> Result<Vec<AppStatus>> {
+		self.get_selected_apps()?
+			.into_iter()
+			.map(|name| {
+				let files = self.apps[&name]
+					.status(self)
+					.map_err(|e| e.with_app(&name))?;
+				Ok(AppStatus { name, files })
+			})
+			.collect()
+	}
+
+	/// Return changes of files by `action` (backup or setup) of selected apps,
+	/// without running anything
+	pub fn diff(&self, action: &Action) -> Result<Vec<AppChanges>> {
+		self.get_selected_apps()?
+			.into_iter()
+			.map(|name| {
+				let changes = self.apps[&name]
+					.diff(action, self)
+					.map_err(|e| e.with_app(&name))?;
+				Ok(AppChanges { name, changes })
+			})
+			.collect()
+	}
+}
@@ -0,0 +1,465 @@
+//! Edit configuration files in place, preserving comments and ordering as far
+//! as possible.
+
+use super::{App, Cli, Config, FileEntry, Format, recipes};
+use crate::{
+	arg_error, did_you_mean,
+	error::{Error, Result},
+	expandhome, info,
+	reporter::LogReporter,
+	warn,
+};
+use std::{
+	fs,
+	path::{self, Path, PathBuf},
+};
+
+/// Keys of file lists in an app
+const FILES_KEYS: [&str; 4] = ["files", "files_linux", "files_macos", "files_windows"];
+
+fn indent(line: &str) -> usize {
+	line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+	let line = line.trim();
+	line.is_empty() || line.starts_with('#')
+}
+
+/// Return the key of a `key: value` line
+fn key_of(line: &str) -> Option<&str> {
+	let line = line.trim_start();
+	let (key, _) = line.split_once(':')?;
+	if key.starts_with(['-', '#', '[', '{']) {
+		return None;
+	}
+
+	Some(key.trim().trim_matches(['"', '\'']))
+}
+
+/// Return the value of a `key: value` line without trailing comment
+fn value_of(line: &str) -> &str {
+	let value = line.split_once(':').map_or("", |(_, value)| value).trim();
+	if value.starts_with('#') { "" } else { value }
+}
+
+/// Remove quotes and trailing comment of a scalar
+fn unquote(scalar: &str) -> &str {
+	let scalar = scalar.trim();
+	let scalar = match scalar.split_once(" #") {
+		Some((scalar, _)) if !scalar.starts_with(['"', '\'']) => scalar.trim(),
+		_ => scalar,
+	};
+	scalar.trim_matches(['"', '\''])
+}
+
+/// Return `s` as a YAML scalar, quoted if needed
+fn scalar(s: &str) -> String {
+	yaml_serde::to_string(s).map_or(format!("{s:?}"), |s| s.trim_end().to_string())
+}
+
+/// Return the end (exclusive) of the block of the line `start` with `indent`,
+/// trailing blank lines are not included.
+fn block_end(lines: &[String], start: usize, indent: usize) -> usize {
+	let mut end = start + 1;
+	for (i, line) in lines.iter().enumerate().skip(start + 1) {
+		if is_blank(line) {
+			continue;
+		}
+		if self::indent(line) <= indent {
+			break;
+		}
+		end = i + 1;
+	}
+
+	end
+}
+
+/// Return the indentation of the first non-blank line in `lines[start..end]`
+fn child_indent(lines: &[String], start: usize, end: usize) -> Option<usize> {
+	lines[start..end]
+		.iter()
+		.find(|line| !is_blank(line))
+		.map(|line| indent(line))
+}
+
+/// Return the line of `key` in `lines[start..end]` whose indentation is
+/// `indent`
+fn find_key(lines: &[String], start: usize, end: usize, indent: usize, key: &str) -> Option<usize> {
+	(start..end).find(|&i| {
+		!is_blank(&lines[i]) && self::indent(&lines[i]) == indent && key_of(&lines[i]) == Some(key)
+	})
+}
+
+/// Return (line, end, child indent) of the block of app `name`, `None` if not
+/// found
+fn find_app(lines: &[String], name: &str) -> Option<(usize, usize, usize)> {
+	let apps = find_key(lines, 0, lines.len(), 0, "apps")?;
+	let apps_end = block_end(lines, apps, 0);
+	let indent = child_indent(lines, apps + 1, apps_end)?;
+	let app = find_key(lines, apps + 1, apps_end, indent, name)?;
+
+	Some((app, block_end(lines, app, indent), indent))
+}
+
+/// Return the end (exclusive) of the block sequence of `key` at line `start`
+fn sequence_end(lines: &[String], start: usize) -> usize {
+	let indent = indent(&lines[start]);
+	let mut end = start + 1;
+	for (i, line) in lines.iter().enumerate().skip(start + 1) {
+		if is_blank(line) {
+			continue;
+		}
+		if self::indent(line) < indent
+			|| (self::indent(line) == indent && !line.trim_start().starts_with('-'))
+		{
+			break;
+		}
+		end = i + 1;
+	}
+
+	end
+}
+
+/// Split a flow sequence `[a, b]` into items and trailing comment, `None` if
+/// `value` is not a one-line flow sequence
+fn flow_items(value: &str) -> Option<(Vec<&str>, &str)> {
+	let (items, rest) = value.strip_prefix('[')?.rsplit_once(']')?;
+	let items = items
+		.split(',')
+		.map(str::trim)
+		.filter(|item| !item.is_empty())
+		.collect();
+
+	Some((items, rest))
+}
+
+fn join(lines: &[String]) -> String {
+	lines.iter().flat_map(|line| [line, "\n"]).collect()
+}
+
+/// Return (line, end, child indent) of the block of app `name`, the app is
+/// created if it does not exist. Return `None` if the structure is not
+/// understood.
+fn app_block(lines: &mut Vec<String>, name: &str) -> Option<(usize, usize, usize)> {
+	let apps = find_key(lines, 0, lines.len(), 0, "apps").unwrap_or_else(|| {
+		lines.push("apps:".to_string());
+		lines.len() - 1
+	});
+	if !value_of(&lines[apps]).is_empty() {
+		return None;
+	}
+
+	let apps_end = block_end(lines, apps, 0);
+	let indent = child_indent(lines, apps + 1, apps_end).unwrap_or(2);
+	let app = find_key(lines, apps + 1, apps_end, indent, name).unwrap_or_else(|| {
+		lines.insert(apps_end, format!("{:indent$}{}:", "", scalar(name)));
+		apps_end
+	});
+	if !value_of(&lines[app]).is_empty() {
+		return None;
+	}
+
+	let end = block_end(lines, app, indent);
+	Some((
+		app,
+		end,
+		child_indent(lines, app + 1, end).unwrap_or(indent + 2),
+	))
+}
+
+/// Add `files` to app `name` in YAML `source`, the app is created if it does
+/// not exist. Return `None` if the structure is not understood.
+#[must_use]
+pub fn add_files(source: &str, name: &str, files: &[String]) -> Option<String> {
+	let mut lines: Vec<String> = source.lines().map(String::from).collect();
+	let items: Vec<String> = files.iter().map(|file| scalar(file)).collect();
+	let sequence = |indent: usize| {
+		let items = items.iter();
+		items.map(move |item| format!("{:indent$}- {item}", ""))
+	};
+
+	let (app, app_end, indent) = app_block(&mut lines, name)?;
+	let Some(key) = find_key(&lines, app + 1, app_end, indent, "files") else {
+		let mut new = vec![format!("{:indent$}files:", "")];
+		new.extend(sequence(indent + 2));
+		let at = app + 1;
+		lines.splice(at..at, new);
+		return Some(join(&lines));
+	};
+
+	let value = value_of(&lines[key]);
+	if value.is_empty() {
+		let end = sequence_end(&lines, key);
+		let indent = child_indent(&lines, key + 1, end).unwrap_or(indent + 2);
+		lines.splice(end..end, sequence(indent));
+	} else {
+		let (mut old, rest) = flow_items(value)?;
+		old.extend(items.iter().map(String::as_str));
+		lines[key] = format!("{:indent$}files: [{}]{rest}", "", old.join(", "));
+	}
+
+	Some(join(&lines))
+}
+
+/// Set the recipe of app `name` in YAML `source`, the app is created if it
+/// does not exist. Return `None` if the structure is not understood.
+#[must_use]
+pub fn set_recipe(source: &str, name: &str, recipe: &str) -> Option<String> {
+	let mut lines: Vec<String> = source.lines().map(String::from).collect();
+	let (app, app_end, indent) = app_block(&mut lines, name)?;
+	let line = format!("{:indent$}recipe: {}", "", scalar(recipe));
+
+	match find_key(&lines, app + 1, app_end, indent, "recipe") {
+		Some(key) => lines[key] = line,
+		None => lines.insert(app + 1, line),
+	}
+
+	Some(join(&lines))
+}
+
+/// Remove app `name` from YAML `source`. Return `None` if the structure is not
+/// understood.
+#[must_use]
+pub fn remove_app(source: &str, name: &str) -> Option<String> {
+	let mut lines: Vec<String> = source.lines().map(String::from).collect();
+	let (app, app_end, _) = find_app(&lines, name)?;
+	lines.drain(app..app_end);
+
+	Some(join(&lines))
+}
+
+/// Remove entries matching `files` (expanded) from app `name` in YAML
+/// `source`. Return `None` if the structure is not understood.
+#[must_use]
+pub fn remove_files(source: &str, name: &str, files: &[PathBuf]) -> Option<String> {
+	let mut lines: Vec<String> = source.lines().map(String::from).collect();
+	let (app, app_end, app_indent) = find_app(&lines, name)?;
+	let indent = child_indent(&lines, app + 1, app_end).unwrap_or(app_indent + 2);
+	let matches = |item: &str| files.contains(&expandhome(&unquote(item)));
+
+	let mut removed = Vec::new();
+	for key_name in FILES_KEYS {
+		let Some(key) = find_key(&lines, app + 1, app_end, indent, key_name) else {
+			continue;
+		};
+		let value = value_of(&lines[key]);
+
+		if value.is_empty() {
+			let end = sequence_end(&lines, key);
+			for (i, line) in lines.iter().enumerate().take(end).skip(key + 1) {
+				if let Some(item) = line.trim_start().strip_prefix("- ")
+					&& matches(item)
+				{
+					removed.push(i);
+				}
+			}
+		} else {
+			let (items, rest) = flow_items(value)?;
+			let items: Vec<&str> = items.into_iter().filter(|item| !matches(item)).collect();
+			lines[key] = format!("{:indent$}{key_name}: [{}]{rest}", "", items.join(", "));
+		}
+	}
+
+	for i in removed.into_iter().rev() {
+		lines.remove(i);
+	}
+
+	Some(join(&lines))
+}
+
+impl Cli {
+	/// Return how `file` should be written in the configuration, the file must
+	/// be under the dotfile root
+	fn config_file_path(&self, file: &Path) -> Result<PathBuf> {
+		let dotfile_root = self.config.get_dotfile_root();
+		let absolute = |path: &Path| {
+			path::absolute(expandhome(&path)).map_err(|e| Error::io("resolve", path, e))
+		};
+		let file = absolute(file)?;
+		let root = absolute(&dotfile_root)?;
+
+		let Ok(relative) = file.strip_prefix(&root) else {
+			return Err(arg_error!(
+				"{} is not under the dotfile root ({})",
+				file.display(),
+				dotfile_root.display()
+			));
+		};
+
+		Ok(if dotfile_root.is_relative() {
+			dotfile_root.join(relative)
+		} else if let Some(home) = dirs::home_dir()
+			&& let Ok(relative) = file.strip_prefix(home)
+		{
+			Path::new("~").join(relative)
+		} else {
+			file
+		})
+	}
+
+	/// Write `expected` to the configuration file. Write `edited` instead if
+	/// it is parsed to the same configuration, so that comments are kept.
+	///
+	/// Return the configuration with recipes applied.
+	fn write_config(&self, expected: &Config, edited: Option<String>) -> Result<Config> {
+		let path = self
+			.config
+			.path
+			.as_ref()
+			.ok_or(arg_error!("no configuration file to edit"))?;
+		let format = Format::from_path(path);
+		let mut resolved = expected.clone();
+		resolved.apply_recipes()?;
+		resolved.resolve_dependencies(&resolved.apps.keys().cloned().collect::<Vec<_>>())?;
+
+		let content = match edited {
+			Some(edited)
+				if format == Format::Yaml
+					&& Config::parse_as(&edited, format, false)
+						.is_ok_and(|config| config == resolved) =>
+			{
+				edited
+			}
+			_ => {
+				if format != Format::Json {
+					warn!("comments and formatting of the configuration are not preserved");
+				}
+				format.serialize(expected)?
+			}
+		};
+
+		fs::write(path, content).map_err(|e| Error::io("write", path, e))?;
+
+		Ok(resolved)
+	}
+
+	/// Read the configuration file as it is, without CLI arguments and recipes
+	/// applied
+	fn read_config(&self) -> Result<(String, Config)> {
+		let path = self
+			.config
+			.path
+			.as_ref()
+			.ok_or(arg_error!("no configuration file to edit"))?;
+		let source = fs::read_to_string(path).map_err(|e| Error::io("read", path, e))?;
+		let config = Format::from_path(path).parse(&source)?;
+
+		Ok((source, config))
+	}
+
+	/// Add `files` to app `name` in the configuration file, and set its recipe
+	/// if `recipe` is given. Back up the new entry if `backup_now` is true.
+	pub fn add(&self, name: &str, files: &[PathBuf]) -> Result<()> {
+		let (source, mut expected) = self.read_config()?;
+		let app = expected.apps.entry(name.to_string()).or_default();
+
+		let mut edited = Some(source);
+		let recipe = self
+			.recipe
+			.as_ref()
+			.filter(|&recipe| app.recipe.as_ref() != Some(recipe));
+		if let Some(recipe) = recipe {
+			if !recipes().contains_key(recipe) {
+				return Err(arg_error!(
+					"unknown recipe: {recipe}{}",
+					did_you_mean(recipe, recipes().keys().map(String::as_str))
+				));
+			}
+			app.recipe = Some(recipe.clone());
+			edited = edited.and_then(|source| set_recipe(&source, name, recipe));
+		}
+
+		let mut added = Vec::new();
+		for file in files {
+			let file = self.config_file_path(file)?;
+			if !expandhome(&file).exists() {
+				warn!("file not found: {}", file.display());
+			}
+			if app.files.iter().any(|entry| *entry.path() == file) || added.contains(&file) {
+				info!("{} is already in {name}", file.display());
+			} else {
+				added.push(file);
+			}
+		}
+		if added.is_empty() && recipe.is_none() {
+			return Ok(());
+		}
+		app.files.extend(added.iter().cloned().map(FileEntry::from));
+
+		let added_str: Vec<String> = added.iter().map(|f| f.display().to_string()).collect();
+		if !added.is_empty() {
+			edited = edited.and_then(|source| add_files(&source, name, &added_str));
+		}
+		let mut config = self.write_config(&expected, edited)?;
+		if let Some(recipe) = recipe {
+			info!("Set recipe of {name} to {recipe}");
+		}
+		if !added.is_empty() {
+			info!("Added {} to {name}", added_str.join(", "));
+		}
+
+		if self.backup_now {
+			config.clean = self.config.clean;
+			let mut app = config.apps[name].clone();
+			if recipe.is_none() {
+				app = App {
+					files: added.into_iter().map(FileEntry::from).collect(),
+					files_linux: Vec::new(),
+					files_macos: Vec::new(),
+					files_windows: Vec::new(),
+					..app
+				};
+			}
+			app.backup(name, &config, &LogReporter::new())?;
+		}
+
+		Ok(())
+	}
+
+	/// Remove `files` from app `name` in the configuration file, or remove the
+	/// app if `files` is empty.
+	pub fn remove(&self, name: &str, files: &[PathBuf]) -> Result<()> {
+		let (source, mut expected) = self.read_config()?;
+		if !expected.apps.contains_key(name) {
+			return Err(arg_error!("app not found: {name}"));
+		}
+
+		if files.is_empty() {
+			expected.apps.remove(name);
+			self.write_config(&expected, remove_app(&source, name))?;
+			info!("Removed {name}");
+			return Ok(());
+		}
+
+		let files: Vec<PathBuf> = files
+			.iter()
+			.map(|file| self.config_file_path(file).map(|file| expandhome(&file)))
+			.collect::<Result<_>>()?;
+		let Some(app) = expected.apps.get_mut(name) else {
+			return Err(arg_error!("app not found: {name}"));
+		};
+		for file in &files {
+			let lists = [
+				&mut app.files,
+				&mut app.files_linux,
+				&mut app.files_macos,
+				&mut app.files_windows,
+			];
+			let mut found = false;
+			for list in lists {
+				let len = list.len();
+				list.retain(|f| expandhome(f.path()) != *file);
+				found |= list.len() != len;
+			}
+			if !found {
+				return Err(arg_error!("{} is not in {name}", file.display()));
+			}
+		}
+
+		self.write_config(&expected, remove_files(&source, name, &files))?;
+		info!("Removed {} files from {name}", files.len());
+
+		Ok(())
+	}
+}
@@ -0,0 +1,131 @@
+use super::{App, Cli, Config, FileEntry, Format, recipes};
+use crate::{
+	arg_error,
+	error::{Error, Result},
+	info, sys_error,
+};
+use std::{
+	collections::BTreeMap,
+	fs,
+	io::{self, BufRead, Write},
+	path::{self, Path, PathBuf},
+};
+
+/// Default `backup_dir` of generated configurations
+pub const DEFAULT_BACKUP_DIR: &str = "~/dotfiles";
+
+/// Return apps of built-in recipes with dotfiles found in `home`, with the
+/// files found, and tags and `ignore` of the recipe
+#[must_use]
+pub fn scan_dotfiles(home: &Path) -> BTreeMap<String, App> {
+	let mut ret = BTreeMap::new();
+
+	for (name, recipe) in recipes() {
+		// files of recipes are under the home directory
+		let files: Vec<FileEntry> = recipe
+			.get_files()
+			.into_iter()
+			.filter(|file| {
+				file.path()
+					.strip_prefix("~")
+					.is_ok_and(|file| home.join(file).exists())
+			})
+			.collect();
+
+		if !files.is_empty() {
+			ret.insert(
+				name.clone(),
+				App {
+					tags: recipe.tags.clone(),
+					ignore: recipe.ignore.clone(),
+					files,
+					..Default::default()
+				},
+			);
+		}
+	}
+
+	ret
+}
+
+/// Ask a yes/no question on stderr, the default answer is yes. End of input,
+/// e.g., stdin is not interactive, is taken as no.
+fn confirm(question: &str, input: &mut impl BufRead) -> Result<bool> {
+	eprint!("{question} [Y/n] ");
+	io::stderr().flush().map_err(|e| sys_error!("{e}"))?;
+
+	let mut answer = String::new();
+	let n = input
+		.read_line(&mut answer)
+		.map_err(|e| sys_error!("failed to read answer: {e}"))?;
+
+	Ok(n > 0 && !matches!(answer.trim(), "n" | "N" | "no" | "No"))
+}
+
+impl Cli {
+	/// Create a configuration with dotfiles found in the home directory.
+	///
+	/// Ask before adding each app unless `yes` is true.
+	pub fn init(&self) -> Result<()> {
+		let path = self
+			.config
+			.path
+			.as_ref()
+			.ok_or(arg_error!("no configuration path to initialize"))?;
+		if path.exists() {
+			return Err(arg_error!("{} already exists", path.display()));
+		}
+
+		let home = dirs::home_dir().ok_or(sys_error!("home directory is unknown"))?;
+		let mut input = io::stdin().lock();
+		let mut config = Config {
+			backup_dir: PathBuf::from(DEFAULT_BACKUP_DIR),
+			..Default::default()
+		};
+
+		let mut apps = scan_dotfiles(&home);
+		// dotbackup can back up itself
+		if let Ok(file) = path::absolute(path)
+			&& let Ok(file) = file.strip_prefix(&home)
+		{
+			apps.insert(
+				"dotbackup".to_string(),
+				App {
+					files: vec![Path::new("~").join(file).into()],
+					..Default::default()
+				},
+			);
+		}
+
+		for (name, app) in apps {
+			let files: Vec<String> = app
+				.files
+				.iter()
+				.map(|file| file.path().display().to_string())
+				.collect();
+			if self.yes || confirm(&format!("Add {name} ({})?", files.join(", ")), &mut input)? {
+				config.apps.insert(name, app);
+			}
+		}
+
+		let format = Format::from_path(path);
+		let mut content = config.dump(format)?;
+		if format != Format::Json {
+			content = format!("# See dotbackup(5) for the configuration format\n{content}");
+		}
+
+		if let Some(dir) = path.parent()
+			&& !dir.as_os_str().is_empty()
+		{
+			fs::create_dir_all(dir).map_err(|e| Error::io("create directory", dir, e))?;
+		}
+		fs::write(path, content).map_err(|e| Error::io("write", path, e))?;
+		info!(
+			"Created {} with {} apps, backup directory is {DEFAULT_BACKUP_DIR}",
+			path.display(),
+			config.apps.len()
+		);
+
+		Ok(())
+	}
+}
@@ -0,0 +1,187 @@
+//! Machine-readable output of `--output json`.
+
+use super::{Action, Cli, Config};
+use crate::{
+	arg_error,
+	error::{Error, Result},
+	record::{Operation, Recorder},
+	reporter::{LogReporter, Reporter},
+	summary::Summary,
+};
+use serde::Serialize;
+use std::{path::PathBuf, str::FromStr};
+
+/// Output format of list, status, diff, backup and setup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Output {
+	/// human-readable logs
+	#[default]
+	Text,
+	/// JSON on stdout, logs are still written to stderr
+	Json,
+}
+
+impl FromStr for Output {
+	type Err = crate::error::Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match s {
+			"text" => Ok(Self::Text),
+			"json" => Ok(Self::Json),
+			_ => Err(arg_error!(
+				"unknown output format: {s:?}, expected text or json"
+			)),
+		}
+	}
+}
+
+/// Metadata of an app printed by `list`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AppInfo<'a> {
+	pub name: &'a str,
+	/// files on this system, expanded
+	pub files: Vec<PathBuf>,
+	/// backup directory on this system, expanded
+	pub backup_dir: PathBuf,
+	pub tags: &'a [String],
+	pub depends_on: &'a [String],
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub recipe: Option<&'a str>,
+	/// number of hooks of the app
+	pub hooks: usize,
+}
+
+/// Report of a backup or setup run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report {
+	/// `backup` or `setup`
+	pub action: &'static str,
+	pub success: bool,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+	pub operations: Vec<Operation>,
+}
+
+impl Config {
+	/// Return metadata of all apps
+	#[must_use]
+	pub fn app_infos(&self) -> Vec<AppInfo<'_>> {
+		self.apps
+			.iter()
+			.map(|(name, app)| AppInfo {
+				name,
+				files: app
+					.get_files()
+					.iter()
+					.map(|file| crate::expandhome(file.path()))
+					.collect(),
+				backup_dir: app.get_backup_dir(self),
+				tags: &app.tags,
+				depends_on: &app.depends_on,
+				recipe: app.recipe.as_deref(),
+				hooks: app.pre_backup.len()
+					+ app.post_backup.len()
+					+ app.pre_setup.len()
+					+ app.post_setup.len(),
+			})
+			.collect()
+	}
+}
+
+/// Serialize `value` to pretty JSON
+fn json<T: Serialize>(value: &T) -> String {
+	serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+impl Cli {
+	/// List apps in the output format
+	pub fn list(&self) {
+		match self.output {
+			Output::Text => self.config.list_apps(),
+			Output::Json => println!("{}", json(&self.config.app_infos())),
+		}
+	}
+
+	/// Print whether files of selected apps match their backups
+	pub fn status(&self) -> Result<()> {
+		let status = self.config.status()?;
+		if self.output == Output::Json {
+			println!("{}", json(&status));
+			return Ok(());
+		}
+
+		for app in status {
+			let differences: Vec<_> = app.differences().collect();
+			match differences.len() {
+				0 => println!("{}: up to date", app.name),
+				1 => println!("{}: 1 file differs", app.name),
+				n => println!("{}: {n} files differ", app.name),
+			}
+			for file in differences {
+				println!("  {}: {}", file.state, file.path.display());
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Print files a backup, or a setup if `setup`, of selected apps would
+	/// change
+	pub fn diff(&self) -> Result<()> {
+		let action = if self.setup {
+			Action::Setup
+		} else {
+			Action::Backup
+		};
+		let diff = self.config.diff(&action)?;
+		if self.output == Output::Json {
+			println!("{}", json(&diff));
+			return Ok(());
+		}
+
+		for app in diff {
+			match app.changes.len() {
+				0 => println!("{}: no change", app.name),
+				1 => println!("{}: 1 change", app.name),
+				n => println!("{}: {n} changes", app.name),
+			}
+			for change in &app.changes {
+				println!("  {}: {}", change.kind, change.path.display());
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Run `f` as `action` with a reporter logging progress, print a summary,
+	/// and print a report of operations performed if the output format is
+	/// JSON.
+	pub fn report(
+		&self,
+		action: &'static str,
+		f: impl FnOnce(&dyn Reporter) -> Result<()>,
+	) -> Result<()> {
+		let reporter = LogReporter {
+			progress: !self.no_progress,
+		};
+		let summary = Summary::new(&reporter);
+		if self.output == Output::Text {
+			let ret = f(&summary);
+			summary.print(action);
+			return ret;
+		}
+
+		let recorder = Recorder::new(&summary);
+		let ret = f(&recorder);
+		summary.print(action);
+		let report = Report {
+			action,
+			success: ret.is_ok(),
+			error: ret.as_ref().err().map(Error::report),
+			operations: recorder.operations(),
+		};
+		println!("{}", json(&report));
+
+		ret
+	}
+}
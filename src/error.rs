@@ -1,4 +1,9 @@
-use std::fmt::Display;
+use std::{
+	fmt::Display,
+	io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum Type {
@@ -6,10 +11,40 @@ pub enum Type {
 	Argument,
 	System,
 	App,
+	/// a hook returned non-zero
+	Hook,
 	#[default]
 	Unknown,
 }
 
+impl Type {
+	/// Return the exit code of dotbackup and dotsetup for errors of this type
+	#[must_use]
+	pub fn exit_code(&self) -> u8 {
+		match self {
+			Self::Unknown => 1,
+			Self::Argument => 2,
+			Self::Config => 3,
+			Self::System => 4,
+			Self::Hook => 5,
+			Self::App => 6,
+		}
+	}
+
+	/// Return how severe errors of this type are, used to pick the type of
+	/// combined errors
+	fn severity(&self) -> u8 {
+		match self {
+			Self::Unknown => 0,
+			Self::App => 1,
+			Self::Hook => 2,
+			Self::System => 3,
+			Self::Config => 4,
+			Self::Argument => 5,
+		}
+	}
+}
+
 impl Display for Type {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
@@ -17,31 +52,152 @@ impl Display for Type {
 			Self::Argument => write!(f, "CLI argument error"),
 			Self::System => write!(f, "system error"),
 			Self::App => write!(f, "application error"),
+			Self::Hook => write!(f, "hook error"),
 			Self::Unknown => write!(f, "unknown error"),
 		}
 	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Error {
 	pub r#type: Type,
 	pub msg: String,
+	/// file or directory involved
+	pub path: Option<PathBuf>,
+	/// operation that failed, e.g., `copy` or `pre_backup[0]`
+	pub operation: Option<String>,
+	/// app being backed up or set up
+	pub app: Option<String>,
+	/// underlying error
+	pub source: Option<Arc<dyn std::error::Error + Send + Sync>>,
 }
 
 impl Error {
 	#[must_use]
 	pub fn new(r#type: Type, msg: String) -> Self {
-		Self { r#type, msg }
+		Self {
+			r#type,
+			msg,
+			..Default::default()
+		}
+	}
+
+	/// Return a system error of `operation` on `path` caused by `source`
+	#[must_use]
+	pub fn io(operation: &str, path: &Path, source: io::Error) -> Self {
+		Self::new(Type::System, path.display().to_string())
+			.with_path(path)
+			.with_operation(operation)
+			.with_source(source)
+	}
+
+	#[must_use]
+	pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+		self.path = Some(path.into());
+		self
+	}
+
+	#[must_use]
+	pub fn with_operation(mut self, operation: impl Into<String>) -> Self {
+		self.operation = Some(operation.into());
+		self
+	}
+
+	#[must_use]
+	pub fn with_app(mut self, app: impl Into<String>) -> Self {
+		self.app = Some(app.into());
+		self
+	}
+
+	#[must_use]
+	pub fn with_source(mut self, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+		self.source = Some(Arc::new(source));
+		self
+	}
+
+	/// Return the exit code of dotbackup and dotsetup for this error
+	#[must_use]
+	pub fn exit_code(&self) -> u8 {
+		self.r#type.exit_code()
+	}
+
+	/// Return the message of this error followed by messages of its sources,
+	/// e.g., `system error in nvim: copy: a: Permission denied`
+	#[must_use]
+	pub fn report(&self) -> String {
+		let mut ret = self.to_string();
+		let mut source = std::error::Error::source(self);
+		while let Some(e) = source {
+			ret = format!("{ret}: {e}");
+			source = e.source();
+		}
+		ret
+	}
+
+	/// Combine `errors` into one error of the most severe type, listing each
+	/// error with its app and path. The app and path are kept if all errors
+	/// share them. Return `Ok` if there is no error.
+	pub fn combine(mut errors: Vec<Error>) -> Result<()> {
+		match errors.len() {
+			0 => Ok(()),
+			1 => Err(errors.remove(0)),
+			n => {
+				let r#type = errors
+					.iter()
+					.map(|e| e.r#type.clone())
+					.max_by_key(Type::severity)
+					.unwrap_or_default();
+				let msg =
+					errors
+						.iter()
+						.fold(format!("{n} problems found"), |msg, e| match &e.path {
+							Some(path) if !e.msg.contains(&*path.to_string_lossy()) => {
+								format!("{msg}\n  - {} ({})", e.report(), path.display())
+							}
+							_ => format!("{msg}\n  - {}", e.report()),
+						});
+				let first = &errors[0];
+				let app = errors
+					.iter()
+					.all(|e| e.app == first.app)
+					.then(|| first.app.clone());
+				let path = errors
+					.iter()
+					.all(|e| e.path == first.path)
+					.then(|| first.path.clone());
+				Err(Self {
+					app: app.flatten(),
+					path: path.flatten(),
+					..Self::new(r#type, msg)
+				})
+			}
+		}
 	}
 }
 
 impl Display for Error {
+	/// e.g., `system error in nvim: copy: a`, without the source, which is
+	/// returned by `source` instead
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(f, "{}: {}", self.r#type, self.msg)
+		write!(f, "{}", self.r#type)?;
+		if let Some(app) = &self.app {
+			write!(f, " in {app}")?;
+		}
+		write!(f, ": ")?;
+		if let Some(operation) = &self.operation {
+			write!(f, "{operation}: ")?;
+		}
+		write!(f, "{}", self.msg)
 	}
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		self.source
+			.as_deref()
+			.map(|source| source as &(dyn std::error::Error + 'static))
+	}
+}
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -65,3 +221,10 @@ macro_rules! config_error {
 		$crate::error::Error::new($crate::error::Type::Config, format!($($arg)*))
 	};
 }
+
+#[macro_export]
+macro_rules! hook_error {
+	($($arg:tt)*) => {
+		$crate::error::Error::new($crate::error::Type::Hook, format!($($arg)*))
+	};
+}
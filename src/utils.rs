@@ -1,41 +1,53 @@
 #[macro_use]
 pub mod log;
 pub mod expandhome;
+pub mod fields;
+pub mod privileged;
+pub mod record;
+pub mod reporter;
+pub mod suggest;
+pub mod summary;
 
 pub use expandhome::*;
+pub use fields::*;
+pub use suggest::*;
 
-use crate::{error::Result, sys_error};
+use crate::{
+	error::{Error, Result},
+	hook_error, sys_error,
+};
 use glob::Pattern;
+use reporter::{Hook, Reporter};
 use std::{
-	fs,
-	io::{self, Write},
+	env, fs,
+	io::{self, BufRead, BufReader, IsTerminal, Read, Write},
 	path::{Path, PathBuf},
 	process::{Command, Stdio},
+	sync::mpsc,
+	thread,
 };
 
+/// Copy directory `from` to `to` recursively, except files matching `ignore`.
+/// `on_file` is called with each file and its size if it is copied, or `None`
+/// if it is ignored.
 #[allow(clippy::missing_panics_doc)]
 pub fn copy_dir_all(
 	from: impl AsRef<Path>,
 	to: impl AsRef<Path>,
 	ignore: &Vec<Pattern>,
-	verbose: bool,
+	on_file: &mut dyn FnMut(&Path, Option<u64>),
 ) -> io::Result<()> {
 	fs::create_dir_all(&to)?;
 
 	for entry in from.as_ref().read_dir()? {
 		let entry = entry?;
 		let path = entry.path();
-		let mut is_ignored = false;
-
-		for pattern in ignore {
-			if pattern.matches_path(&PathBuf::from(path.file_name().unwrap())) {
-				log!(verbose, "ignore {}", path.display());
-				is_ignored = true;
-				break;
-			}
-		}
-
-		if is_ignored {
+		let file_name = PathBuf::from(path.file_name().unwrap());
+		if ignore
+			.iter()
+			.any(|pattern| pattern.matches_path(&file_name))
+		{
+			on_file(&path, None);
 			continue;
 		}
 
@@ -44,54 +56,211 @@ pub fn copy_dir_all(
 				entry.path(),
 				to.as_ref().join(entry.file_name()),
 				ignore,
-				verbose,
+				on_file,
 			)?;
 		} else {
-			let from = entry.path();
 			let to = to.as_ref().join(entry.file_name());
-			log!(verbose, "copy {} -> {}", from.display(), to.display());
-			fs::copy(from, to)?;
+			log!("copy {} -> {}", path.display(), to.display());
+			let bytes = fs::copy(&path, to)?;
+			on_file(&path, Some(bytes));
 		}
 	}
 	Ok(())
 }
 
+/// Send lines read from `pipe` to `sender` as they arrive
+fn forward_lines(pipe: impl Read, sender: &mpsc::Sender<String>) {
+	let mut reader = BufReader::new(pipe);
+	let mut line = Vec::new();
+	while reader.read_until(b'\n', &mut line).is_ok_and(|n| n > 0) {
+		if sender
+			.send(String::from_utf8_lossy(&line).into_owned())
+			.is_err()
+		{
+			break;
+		}
+		line.clear();
+	}
+}
+
 pub fn run_hook(script: &str, backup_dir: &Path) -> Result<()> {
+	run_hook_to(script, backup_dir, false)
+}
+
+/// Run hook `script`, writing its stdout to stderr if `to_stderr`
+fn run_hook_to(script: &str, backup_dir: &Path, to_stderr: bool) -> Result<()> {
 	// TODO: Windows implementation
+	trace!("hook script:\n{}", script.trim_end());
+	// pipe the output through the logger if it is captured, or if it is
+	// mirrored to a log file and the hook does not write to a terminal, so
+	// that interactive hooks keep their terminal
+	let piped = log::is_capturing()
+		|| log::has_sink()
+		|| (log::has_log_file() && !io::stdout().is_terminal() && !io::stderr().is_terminal());
 	let script = format!("set -ex\n{script}");
-	let mut sh = Command::new("sh")
+	let mut command = Command::new("sh");
+	command
 		.arg("-s")
 		.env("BACKUP_DIR", backup_dir)
-		.stdin(Stdio::piped())
+		.stdin(Stdio::piped());
+	if piped {
+		command.stdout(Stdio::piped()).stderr(Stdio::piped());
+	} else if !log::enabled(log::Level::Normal) {
+		command.stdout(Stdio::null());
+	} else if to_stderr {
+		command.stdout(io::stderr());
+	}
+	let mut sh = command
 		.spawn()
-		.map_err(|e| sys_error!("failed to spawn sh: {e}"))?;
+		.map_err(|e| sys_error!("failed to spawn sh").with_source(e))?;
 
-	sh.stdin
+	let mut stdin = sh
+		.stdin
 		.take()
-		.ok_or(sys_error!("failed to open stdin of sh"))?
-		.write_all(script.as_bytes())
-		.map_err(|e| sys_error!("failed to write stdin of sh: {e}"))?;
+		.ok_or(sys_error!("failed to open stdin of sh"))?;
+
+	// write the script in another thread, otherwise sh may block on a full
+	// stdout pipe while we are still writing
+	let status = thread::scope(|s| {
+		let writer = s.spawn(move || match stdin.write_all(script.as_bytes()) {
+			// sh exited early, leave it to the exit status
+			Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+			ret => ret,
+		});
 
-	let status = sh.wait().map_err(|e| sys_error!("{e}"))?;
+		// log lines of both pipes as they arrive, in this thread because
+		// capturing is per thread
+		let (sender, receiver) = mpsc::channel();
+		if let Some(stdout) = sh.stdout.take() {
+			let sender = sender.clone();
+			s.spawn(move || forward_lines(stdout, &sender));
+		}
+		if let Some(stderr) = sh.stderr.take() {
+			let sender = sender.clone();
+			s.spawn(move || forward_lines(stderr, &sender));
+		}
+		drop(sender);
+		for line in receiver {
+			log::print(log::Level::Normal, &line);
+		}
+
+		let status = sh
+			.wait()
+			.map_err(|e| sys_error!("failed to wait for sh").with_source(e));
+		writer
+			.join()
+			.map_err(|_| sys_error!("failed to write stdin of sh"))?
+			.map_err(|e| sys_error!("failed to write stdin of sh").with_source(e))?;
+		status
+	})?;
 
 	if status.success() {
 		Ok(())
 	} else {
-		unsafe {
-			Err(sys_error!(
-				"sh returned non-zero: {}",
-				status.code().unwrap_unchecked()
-			))
-		}
+		Err(match status.code() {
+			Some(code) => hook_error!("sh returned non-zero: {code}"),
+			None => hook_error!("sh was terminated by a signal"),
+		})
 	}
 }
 
-pub fn run_hooks(hooks: &[String], backup_dir: &Path, name: &str) -> Result<()> {
-	let n = hooks.len();
-	for (i, hook) in hooks.iter().enumerate() {
-		info!("Running {name} [{}/{n}]", i + 1);
-		run_hook(hook, backup_dir)?;
+/// Run `hooks` of `stage` (e.g., `pre_backup`) of `app`, or global hooks if
+/// `app` is `None`.
+pub fn run_hooks(
+	hooks: &[String],
+	backup_dir: &Path,
+	stage: &str,
+	app: Option<&str>,
+	reporter: &dyn Reporter,
+) -> Result<()> {
+	for (index, script) in hooks.iter().enumerate() {
+		let hook = Hook {
+			app,
+			stage,
+			index,
+			total: hooks.len(),
+			script,
+		};
+		reporter.hook_started(&hook);
+		let ret = run_hook_to(script, backup_dir, reporter.hooks_to_stderr())
+			.map_err(|e| e.with_operation(format!("{stage}[{index}]")));
+		reporter.hook_finished(&hook, &ret);
+		ret?;
 	}
 
 	Ok(())
 }
+
+/// Return the path of `program` found in `PATH`.
+#[must_use]
+pub fn find_program(program: &str) -> Option<PathBuf> {
+	let program = format!("{program}{}", env::consts::EXE_SUFFIX);
+	env::split_paths(&env::var_os("PATH")?)
+		.map(|dir| dir.join(&program))
+		.find(|path| path.is_file())
+}
+
+/// Set permission bits of `path` to `mode`, do nothing on non-Unix systems
+pub fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		fs::set_permissions(path, fs::Permissions::from_mode(mode))
+	}
+	#[cfg(not(unix))]
+	{
+		let _ = (path, mode);
+		Ok(())
+	}
+}
+
+/// Set owner and group of `path`, do nothing on non-Unix systems
+pub fn set_owner(path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+	#[cfg(unix)]
+	{
+		std::os::unix::fs::chown(path, Some(uid), Some(gid))
+	}
+	#[cfg(not(unix))]
+	{
+		let _ = (path, uid, gid);
+		Ok(())
+	}
+}
+
+/// Check whether files can be created in `dir`, which may not exist yet.
+pub fn check_writable(dir: &Path) -> Result<()> {
+	let mut existing = dir;
+	while !existing.exists() {
+		existing = match existing.parent() {
+			Some(parent) if parent.as_os_str().is_empty() => Path::new("."),
+			Some(parent) => parent,
+			None => return Err(sys_error!("{} cannot be created", dir.display())),
+		};
+	}
+
+	let metadata = fs::metadata(existing).map_err(|e| Error::io("check directory", existing, e))?;
+	if !metadata.is_dir() {
+		return Err(sys_error!(
+			"{} cannot be created: {} is not a directory",
+			dir.display(),
+			existing.display()
+		));
+	}
+
+	// permission bits do not tell about the owner, ACLs or read-only mounts,
+	// so try to create a file
+	let probe = existing.join(format!(".dotbackup-probe-{}", std::process::id()));
+	match fs::OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.open(&probe)
+	{
+		Ok(_) => fs::remove_file(&probe).map_err(|e| Error::io("remove file", &probe, e)),
+		Err(e) => Err(sys_error!(
+			"{} is not writable: cannot create files in {}",
+			dir.display(),
+			existing.display()
+		)
+		.with_source(e)),
+	}
+}
@@ -0,0 +1,62 @@
+use serde::{
+	Deserialize, Deserializer,
+	de::{self, Visitor},
+	forward_to_deserialize_any,
+};
+use std::fmt::Display;
+
+/// Return field names of struct `T` as seen by serde, so that they never drift
+/// from what is actually accepted.
+///
+/// Return an empty slice if `T` is not a struct.
+#[must_use]
+pub fn field_names<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+	let mut fields = None;
+	let _ = T::deserialize(FieldNames(&mut fields));
+
+	fields.unwrap_or_default()
+}
+
+/// A deserializer that records struct fields and then fails
+struct FieldNames<'a>(&'a mut Option<&'static [&'static str]>);
+
+#[derive(Debug)]
+struct Stop;
+
+impl Display for Stop {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "stop")
+	}
+}
+
+impl std::error::Error for Stop {}
+
+impl de::Error for Stop {
+	fn custom<T: Display>(_msg: T) -> Self {
+		Self
+	}
+}
+
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+	type Error = Stop;
+
+	fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Stop> {
+		Err(Stop)
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		fields: &'static [&'static str],
+		_visitor: V,
+	) -> Result<V::Value, Stop> {
+		*self.0 = Some(fields);
+		Err(Stop)
+	}
+
+	forward_to_deserialize_any! {
+		bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+		bytes byte_buf option unit unit_struct newtype_struct seq tuple
+		tuple_struct map enum identifier ignored_any
+	}
+}
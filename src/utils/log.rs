@@ -1,47 +1,337 @@
+//! Logger of dotbackup.
+//!
+//! Messages are written to stderr by default. Library users can redirect them
+//! to a sink with `set_sink`, and `set_log_file` mirrors them to a file. Colors
+//! are used only if stderr is a terminal and `NO_COLOR` is not set.
+
+use std::{
+	cell::RefCell,
+	env,
+	fs::File,
+	io::{self, IsTerminal, Write},
+	sync::{
+		Mutex, OnceLock, PoisonError, RwLock,
+		atomic::{AtomicBool, AtomicU8, Ordering},
+	},
+	time::{Duration, Instant},
+};
+
+/// Verbosity level of the logger, also the level of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Level {
+	/// only errors
+	Quiet,
+	/// errors, warnings, progress and hook output
+	#[default]
+	Normal,
+	/// also details of each operation
+	Verbose,
+	/// also everything else, e.g., scripts of hooks
+	Trace,
+}
+
+impl Level {
+	const ALL: [Level; 4] = [Level::Quiet, Level::Normal, Level::Verbose, Level::Trace];
+}
+
+/// A function receiving messages instead of stderr
+pub type Sink = Box<dyn Fn(Level, &str) + Send + Sync>;
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+static SINK: RwLock<Option<Sink>> = RwLock::new(None);
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+/// 0 means auto-detect
+static COLOR: AtomicU8 = AtomicU8::new(0);
+/// whether a status line is shown at the bottom of stderr
+static STATUS: AtomicBool = AtomicBool::new(false);
+/// when the status line was drawn last time
+static STATUS_TIME: Mutex<Option<Instant>> = Mutex::new(None);
+
+thread_local! {
+	/// Buffer of the current thread's messages, `None` if not capturing
+	static CAPTURE: RefCell<Option<Captured>> = const { RefCell::new(None) };
+}
+
+/// Messages captured by `capture`
+pub type Captured = Vec<(Level, String)>;
+
+/// Set the verbosity level
+pub fn set_level(level: Level) {
+	LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Return the verbosity level
+#[must_use]
+pub fn level() -> Level {
+	Level::ALL[usize::from(LEVEL.load(Ordering::Relaxed))]
+}
+
+/// Whether messages of `level` are written
+#[must_use]
+pub fn enabled(level: Level) -> bool {
+	level <= self::level()
+}
+
+/// Write messages to `sink` instead of stderr, or to stderr if `None`
+///
+/// # Panics
+///
+/// Will panic if the sink lock is poisoned
+pub fn set_sink(sink: Option<Sink>) {
+	*SINK.write().unwrap() = sink;
+}
+
+/// Mirror messages to `file`, without colors
+///
+/// # Panics
+///
+/// Will panic if the log file lock is poisoned
+pub fn set_log_file(file: Option<File>) {
+	*LOG_FILE.lock().unwrap() = file;
+}
+
+/// Whether messages go to a sink instead of stderr
+///
+/// # Panics
+///
+/// Will panic if the sink lock is poisoned
+#[must_use]
+pub fn has_sink() -> bool {
+	SINK.read().unwrap().is_some()
+}
+
+/// Whether messages are mirrored to a log file
+///
+/// # Panics
+///
+/// Will panic if the log file lock is poisoned
+#[must_use]
+pub fn has_log_file() -> bool {
+	LOG_FILE.lock().unwrap().is_some()
+}
+
+/// Force colors on or off, `None` means auto-detect
+pub fn set_color(color: Option<bool>) {
+	COLOR.store(
+		match color {
+			None => 0,
+			Some(true) => 1,
+			Some(false) => 2,
+		},
+		Ordering::Relaxed,
+	);
+}
+
+/// Whether messages are colored
+///
+/// # Panics
+///
+/// Will panic if the sink lock is poisoned
+#[must_use]
+pub fn color() -> bool {
+	static AUTO: OnceLock<bool> = OnceLock::new();
+	match COLOR.load(Ordering::Relaxed) {
+		1 => true,
+		2 => false,
+		_ => {
+			SINK.read().unwrap().is_none()
+				&& *AUTO.get_or_init(|| {
+					env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+						&& io::stderr().is_terminal()
+				})
+		}
+	}
+}
+
+/// Return `text` in `color` if colors are enabled
+#[must_use]
+pub fn paint(color: &str, text: &str) -> String {
+	if self::color() {
+		format!("{color}{text}{}", crate::consts::colors::RESET)
+	} else {
+		text.to_string()
+	}
+}
+
+/// Remove ANSI escape sequences from `text`
+fn strip(text: &str) -> String {
+	let mut ret = String::with_capacity(text.len());
+	let mut chars = text.chars();
+	while let Some(c) = chars.next() {
+		if c == '\x1b' {
+			chars.by_ref().find(char::is_ascii_alphabetic);
+		} else {
+			ret.push(c);
+		}
+	}
+	ret
+}
+
+/// Whether stderr is a terminal that messages are written to directly
+#[must_use]
+pub fn is_interactive() -> bool {
+	static TERMINAL: OnceLock<bool> = OnceLock::new();
+	!is_capturing()
+		&& SINK
+			.read()
+			.unwrap_or_else(PoisonError::into_inner)
+			.is_none()
+		&& *TERMINAL.get_or_init(|| io::stderr().is_terminal())
+}
+
+/// Show `text` as a status line at the bottom of stderr, replacing the
+/// previous one, which is cleared by the next message.
+///
+/// Do nothing if stderr is not interactive or the level is quiet. The line is
+/// redrawn at most 10 times per second.
+pub fn status(text: &str) {
+	if !enabled(Level::Normal) || !is_interactive() {
+		return;
+	}
+	let mut time = STATUS_TIME.lock().unwrap_or_else(PoisonError::into_inner);
+	let now = Instant::now();
+	if time.is_some_and(|time| now.duration_since(time) < Duration::from_millis(100)) {
+		return;
+	}
+	*time = Some(now);
+	eprint!("\r\x1b[K{text}");
+	STATUS.store(true, Ordering::Relaxed);
+}
+
+/// Write `text` of `level` to the sink or stderr, and the log file
+fn emit(level: Level, text: &str) {
+	if STATUS.swap(false, Ordering::Relaxed) {
+		eprint!("\r\x1b[K");
+	}
+	if let Some(file) = LOG_FILE
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.as_mut()
+	{
+		let _ = file.write_all(strip(text).as_bytes());
+	}
+	match SINK.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+		Some(sink) => sink(level, text),
+		None => eprint!("{text}"),
+	}
+}
+
+/// Write `text` of `level` regardless of the verbosity level, or to the
+/// capture buffer if the current thread is capturing.
+pub fn write(level: Level, text: &str) {
+	CAPTURE.with_borrow_mut(|capture| match capture {
+		Some(buffer) => buffer.push((level, text.to_string())),
+		None => emit(level, text),
+	});
+}
+
+/// Write `text` of `level` if the level is enabled
+pub fn print(level: Level, text: &str) {
+	if enabled(level) {
+		write(level, text);
+	}
+}
+
+/// Write a `==>` message of `level` in `color`
+pub fn message(level: Level, color: &str, args: std::fmt::Arguments) {
+	write(level, &format!("{} {args}\n", paint(color, "==>")));
+}
+
+/// Write messages captured by another thread
+pub fn replay(captured: Captured) {
+	for (level, text) in captured {
+		write(level, &text);
+	}
+}
+
+/// Whether log output of the current thread is being captured.
+#[must_use]
+pub fn is_capturing() -> bool {
+	CAPTURE.with_borrow(Option::is_some)
+}
+
+/// Run `f` with log output of the current thread captured, return its result
+/// and the captured messages.
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Captured) {
+	let outer = CAPTURE.replace(Some(Vec::new()));
+	let ret = f();
+	let captured = CAPTURE.replace(outer).unwrap_or_default();
+
+	(ret, captured)
+}
+
 #[macro_export]
 macro_rules! error {
 	() => {
-		eprintln!();
+		$crate::utils::log::write($crate::utils::log::Level::Quiet, "\n");
+	};
+	($($arg:tt)*) => {
+		$crate::utils::log::message(
+			$crate::utils::log::Level::Quiet,
+			$crate::consts::colors::RED,
+			format_args!($($arg)*),
+		);
 	};
-	($($arg:tt)*) => {{
-		eprint!("{}==>{} ", $crate::consts::colors::RED, $crate::consts::colors::RESET);
-		eprintln!($($arg)*);
-	}};
 }
 
 #[macro_export]
 macro_rules! warn {
 	() => {
-		eprintln!();
+		$crate::utils::log::print($crate::utils::log::Level::Normal, "\n");
+	};
+	($($arg:tt)*) => {
+		if $crate::utils::log::enabled($crate::utils::log::Level::Normal) {
+			$crate::utils::log::message(
+				$crate::utils::log::Level::Normal,
+				$crate::consts::colors::YELLOW,
+				format_args!($($arg)*),
+			);
+		}
 	};
-	($($arg:tt)*) => {{
-		eprint!("{}==>{} ", $crate::consts::colors::YELLOW, $crate::consts::colors::RESET);
-		eprintln!($($arg)*);
-	}};
 }
 
 #[macro_export]
 macro_rules! info {
 	() => {
-		eprintln!();
+		$crate::utils::log::print($crate::utils::log::Level::Normal, "\n");
+	};
+	($($arg:tt)*) => {
+		if $crate::utils::log::enabled($crate::utils::log::Level::Normal) {
+			$crate::utils::log::message(
+				$crate::utils::log::Level::Normal,
+				$crate::consts::colors::GREEN,
+				format_args!($($arg)*),
+			);
+		}
 	};
-	($($arg:tt)*) => {{
-		eprint!("{}==>{} ", $crate::consts::colors::GREEN, $crate::consts::colors::RESET);
-		eprintln!($($arg)*);
-	}};
 }
 
+/// Log a message if the level is at least verbose
 #[macro_export]
 macro_rules! log {
-	($verbose:expr) => {
-		if $verbose {
-			eprintln!();
+	() => {
+		$crate::utils::log::print($crate::utils::log::Level::Verbose, "\n");
+	};
+	($($arg:tt)*) => {
+		if $crate::utils::log::enabled($crate::utils::log::Level::Verbose) {
+			$crate::utils::log::message(
+				$crate::utils::log::Level::Verbose,
+				$crate::consts::colors::CYAN,
+				format_args!($($arg)*),
+			);
 		}
 	};
-	($verbose:expr, $($arg:tt)*) => {{
-		if $verbose {
-		eprint!("{}==>{} ", $crate::consts::colors::CYAN, $crate::consts::colors::RESET);
-		eprintln!($($arg)*);
+}
+
+#[macro_export]
+macro_rules! trace {
+	($($arg:tt)*) => {
+		if $crate::utils::log::enabled($crate::utils::log::Level::Trace) {
+			$crate::utils::log::message(
+				$crate::utils::log::Level::Trace,
+				$crate::consts::colors::MAGENTA,
+				format_args!($($arg)*),
+			);
 		}
-	}};
+	};
 }
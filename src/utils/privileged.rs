@@ -0,0 +1,440 @@
+//! File operations of privileged apps, run as root through a helper such as
+//! sudo or doas.
+
+use crate::{config_error, error::Result, find_program, sys_error};
+use glob::Pattern;
+use std::{
+	ffi::OsStr,
+	fs, io,
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+};
+
+/// Helpers tried in order if none is configured
+const HELPERS: [&str; 2] = ["sudo", "doas"];
+
+/// Print `mode uid gid size path` of `$1` and everything in it, with GNU or
+/// BSD stat, nothing if it does not exist
+const WALK_SCRIPT: &str = r#"[ -e "$1" ] || exit 0
+if stat -c %f / >/dev/null 2>&1; then
+	find -H "$1" -exec stat -L -c '%f %u %g %s %n' -- {} +
+else
+	find -H "$1" -exec stat -L -f '%Xp %u %g %z %N' -- {} +
+fi"#;
+
+/// Create the parent of `$3`, then for each `type from to` after it, create
+/// directory `to` (type `d`) or copy file `from` to `to` with `$1`, then
+/// change the owner of `$3` recursively to `$2` if not empty
+const COPY_SCRIPT: &str = r#"cp=$1 owner=$2 root=$3
+shift 3
+mkdir -p -- "$(dirname -- "$root")" || exit
+while [ $# -gt 0 ]; do
+	if [ "$1" = d ]; then mkdir -p -- "$3"; else $cp -- "$2" "$3"; fi || exit
+	shift 3
+done
+[ -z "$owner" ] || chown -R -- "$owner" "$root""#;
+
+/// Maximum length of arguments passed to one shell, far below `ARG_MAX` of
+/// supported systems
+const MAX_ARGS_LEN: usize = 64 * 1024;
+
+/// Configurations of doas, the first readable one is used
+const DOAS_CONFS: [&str; 2] = ["/etc/doas.conf", "/usr/local/etc/doas.conf"];
+
+/// Whether rules in doas configuration `conf` remember or skip
+/// authentication, with `persist` or `nopass`
+#[must_use]
+pub fn doas_persists(conf: &str) -> bool {
+	conf.lines()
+		.filter_map(|line| line.split('#').next())
+		.flat_map(str::split_whitespace)
+		.any(|word| word == "persist" || word == "nopass")
+}
+
+/// Split `args` into batches of at most `MAX_ARGS_LEN` bytes, keeping each
+/// `group` of arguments in one batch. Return one empty batch if `args` is
+/// empty.
+fn batches<'a, 'b>(args: &'a [&'b OsStr], group: usize) -> Vec<&'a [&'b OsStr]> {
+	let mut batches = Vec::new();
+	let (mut start, mut len) = (0, 0);
+	for (i, chunk) in args.chunks(group).enumerate() {
+		let chunk_len: usize = chunk.iter().map(|arg| arg.len() + 1).sum();
+		if len > 0 && len + chunk_len > MAX_ARGS_LEN {
+			batches.push(&args[start..i * group]);
+			start = i * group;
+			len = 0;
+		}
+		len += chunk_len;
+	}
+	if start < args.len() || batches.is_empty() {
+		batches.push(&args[start..]);
+	}
+	batches
+}
+
+/// A file or directory found by `Helper::walk` or `walk_local`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+	/// path relative to the walked path, empty for the walked path itself
+	pub path: PathBuf,
+	pub is_dir: bool,
+	/// permission bits
+	pub mode: u32,
+	pub uid: u32,
+	pub gid: u32,
+	pub size: u64,
+}
+
+impl Entry {
+	/// Return the path of this entry in `root`
+	#[must_use]
+	pub fn path_in(&self, root: &Path) -> PathBuf {
+		if self.path.as_os_str().is_empty() {
+			root.to_path_buf()
+		} else {
+			root.join(&self.path)
+		}
+	}
+
+	/// Parse a line of `WALK_SCRIPT` printed for `root`
+	fn parse(root: &Path, line: &str) -> Option<Self> {
+		let mut fields = line.splitn(5, ' ');
+		let mode = u32::from_str_radix(fields.next()?, 16).ok()?;
+		let uid = fields.next()?.parse().ok()?;
+		let gid = fields.next()?.parse().ok()?;
+		let size = fields.next()?.parse().ok()?;
+		let path = Path::new(fields.next()?).strip_prefix(root).ok()?;
+		Some(Self {
+			path: path.to_path_buf(),
+			is_dir: mode & 0o170_000 == 0o040_000,
+			mode: mode & 0o7777,
+			uid,
+			gid,
+			size,
+		})
+	}
+}
+
+/// Return entries of `path` like `Helper::walk`, read as the current user,
+/// parents before their children
+pub fn walk_local(path: &Path) -> io::Result<Vec<Entry>> {
+	let mut ret = Vec::new();
+	walk_local_into(path, Path::new(""), &mut ret)?;
+	Ok(ret)
+}
+
+fn walk_local_into(root: &Path, relative: &Path, ret: &mut Vec<Entry>) -> io::Result<()> {
+	let mut entry = Entry {
+		path: relative.to_path_buf(),
+		is_dir: false,
+		mode: 0,
+		uid: 0,
+		gid: 0,
+		size: 0,
+	};
+	let path = entry.path_in(root);
+	let metadata = fs::metadata(&path)?;
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+		(entry.mode, entry.uid, entry.gid) =
+			(metadata.mode() & 0o7777, metadata.uid(), metadata.gid());
+	}
+	entry.is_dir = metadata.is_dir();
+	entry.size = metadata.len();
+	ret.push(entry);
+
+	if metadata.is_dir() {
+		let mut names = path
+			.read_dir()?
+			.map(|entry| entry.map(|entry| entry.file_name()))
+			.collect::<io::Result<Vec<_>>>()?;
+		names.sort();
+		for name in names {
+			walk_local_into(root, &relative.join(name), ret)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Remove `entries` of `root` whose names match `ignore`, and everything in
+/// them, calling `on_ignored` with each ignored path like `copy_dir_all`.
+/// `root` itself is never ignored.
+pub fn remove_ignored(
+	root: &Path,
+	entries: &mut Vec<Entry>,
+	ignore: &[Pattern],
+	on_ignored: &mut dyn FnMut(&Path),
+) {
+	let mut ignored: Vec<PathBuf> = Vec::new();
+	entries.retain(|entry| {
+		if ignored.iter().any(|dir| entry.path.starts_with(dir)) {
+			return false;
+		}
+		let Some(name) = entry.path.file_name() else {
+			return true;
+		};
+		if ignore
+			.iter()
+			.any(|pattern| pattern.matches_path(Path::new(name)))
+		{
+			on_ignored(&entry.path_in(root));
+			ignored.push(entry.path.clone());
+			return false;
+		}
+		true
+	});
+}
+
+/// A command running other commands as root, e.g., `sudo` or `doas -n`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Helper {
+	program: PathBuf,
+	args: Vec<String>,
+}
+
+impl Helper {
+	/// Return `helper`, a command split by whitespace, or the first of sudo and
+	/// doas found in `PATH` if it is `None`
+	pub fn new(helper: Option<&str>) -> Result<Self> {
+		if cfg!(not(unix)) {
+			return Err(sys_error!(
+				"privileged apps are only supported on Unix systems"
+			));
+		}
+
+		if let Some(helper) = helper {
+			let mut words = helper.split_whitespace().map(String::from);
+			let program = words
+				.next()
+				.ok_or(config_error!("privilege_helper is empty"))?;
+			return Ok(Self {
+				program: program.into(),
+				args: words.collect(),
+			});
+		}
+
+		HELPERS
+			.iter()
+			.find_map(|program| find_program(program))
+			.map(|program| Self {
+				program,
+				args: Vec::new(),
+			})
+			.ok_or(sys_error!(
+				"sudo or doas is not found in PATH, which is needed by privileged apps"
+			))
+	}
+
+	fn command(&self, args: &[&OsStr]) -> Command {
+		let mut command = Command::new(&self.program);
+		command.args(&self.args).args(args);
+		trace!(
+			"{} {}",
+			self.program.display(),
+			self.args
+				.iter()
+				.map(String::as_str)
+				.chain(args.iter().map(|arg| arg.to_str().unwrap_or("?")))
+				.collect::<Vec<_>>()
+				.join(" ")
+		);
+		command
+	}
+
+	/// Run `args` as root and return its standard output, fail with
+	/// `operation` of `path` if it returns non-zero
+	fn output(&self, operation: &str, path: &Path, args: &[&OsStr]) -> Result<String> {
+		let output = self
+			.command(args)
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.output()
+			.map_err(|e| sys_error!("failed to spawn {}", self.program.display()).with_source(e))?;
+
+		if output.status.success() {
+			Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+		} else {
+			Err(sys_error!(
+				"{} returned non-zero: {}",
+				self.program.display(),
+				String::from_utf8_lossy(&output.stderr).trim()
+			)
+			.with_path(path)
+			.with_operation(operation))
+		}
+	}
+
+	/// Run shell `script` as root with `args` as its positional parameters
+	fn script(
+		&self,
+		operation: &str,
+		path: &Path,
+		script: &str,
+		args: &[&OsStr],
+	) -> Result<String> {
+		let mut command: Vec<&OsStr> = vec!["sh".as_ref(), "-c".as_ref(), script.as_ref()];
+		command.push("sh".as_ref());
+		command.extend(args);
+		self.output(operation, path, &command)
+	}
+
+	/// Return a warning if the helper may ask for the password for every
+	/// command, i.e., doas without `persist` in doas.conf
+	#[must_use]
+	pub fn warning(&self) -> Option<String> {
+		if self.program.file_stem() != Some(OsStr::new("doas"))
+			|| self.args.iter().any(|arg| arg == "-n")
+		{
+			return None;
+		}
+		let conf = DOAS_CONFS
+			.iter()
+			.find_map(|path| fs::read_to_string(path).ok())?;
+
+		(!doas_persists(&conf)).then(|| {
+			format!(
+				"{} may ask for the password for every file of privileged apps, add persist to doas.conf to avoid it",
+				self.program.display()
+			)
+		})
+	}
+
+	/// Ask for the password once so that later commands do not, only for sudo
+	pub fn validate(&self) -> Result<()> {
+		if self.program.file_stem() != Some(OsStr::new("sudo")) {
+			return Ok(());
+		}
+
+		let status = self
+			.command(&[OsStr::new("-v")])
+			.status()
+			.map_err(|e| sys_error!("failed to spawn {}", self.program.display()).with_source(e))?;
+		if status.success() {
+			Ok(())
+		} else {
+			Err(sys_error!("{} -v failed", self.program.display()))
+		}
+	}
+
+	/// Remove `path` recursively if it exists, return whether it existed
+	pub fn remove_all(&self, path: &Path) -> Result<bool> {
+		let script = r#"[ -e "$1" ] || exit 0
+rm -rf -- "$1" && echo removed"#;
+		let output = self.script("remove", path, script, &[path.as_ref()])?;
+		Ok(!output.is_empty())
+	}
+
+	/// Return `path` and everything in it, read as root, parents before their
+	/// children. Return nothing if `path` does not exist.
+	pub fn walk(&self, path: &Path) -> Result<Vec<Entry>> {
+		let output = self.script("read", path, WALK_SCRIPT, &[path.as_ref()])?;
+		Ok(output
+			.lines()
+			.filter_map(|line| Entry::parse(path, line))
+			.collect())
+	}
+
+	/// Copy `entries` of file or directory `from` to the same paths in `to`,
+	/// keeping mode, ownership and timestamps of files if `preserve`.
+	/// Otherwise existing files keep their ownership and mode, and new files
+	/// are owned by root. If `owner_of` is given, `to` is owned by its owner
+	/// recursively afterwards.
+	pub fn copy(
+		&self,
+		from: &Path,
+		to: &Path,
+		entries: &[Entry],
+		preserve: bool,
+		owner_of: Option<&Path>,
+	) -> Result<()> {
+		let owner = match owner_of {
+			#[cfg(unix)]
+			Some(of) => {
+				use std::os::unix::fs::MetadataExt;
+				let metadata =
+					fs::metadata(of).map_err(|e| crate::error::Error::io("check owner", of, e))?;
+				format!("{}:{}", metadata.uid(), metadata.gid())
+			}
+			_ => String::new(),
+		};
+		let paths: Vec<(PathBuf, PathBuf)> = entries
+			.iter()
+			.map(|entry| (entry.path_in(from), entry.path_in(to)))
+			.collect();
+
+		let mut triples: Vec<&OsStr> = Vec::new();
+		for (entry, (from, to)) in entries.iter().zip(&paths) {
+			triples.push(if entry.is_dir { "d" } else { "f" }.as_ref());
+			triples.push(from.as_ref());
+			triples.push(to.as_ref());
+		}
+
+		let cp = if preserve { "cp -P -p" } else { "cp -P" };
+		let batches = batches(&triples, 3);
+		for (i, batch) in batches.iter().enumerate() {
+			// change the owner once everything is copied
+			let owner = if i + 1 == batches.len() {
+				owner.as_str()
+			} else {
+				""
+			};
+			let mut args: Vec<&OsStr> = vec![cp.as_ref(), owner.as_ref(), to.as_ref()];
+			args.extend(*batch);
+			self.script("copy", to, COPY_SCRIPT, &args)?;
+		}
+		Ok(())
+	}
+
+	/// Change owners of `owners` of `path` at once, each path with `uid:gid`
+	pub fn chown(&self, path: &Path, owners: &[(PathBuf, u32, u32)]) -> Result<()> {
+		let owners: Vec<(String, &PathBuf)> = owners
+			.iter()
+			.map(|(path, uid, gid)| (format!("{uid}:{gid}"), path))
+			.collect();
+		self.for_each_pair("change owner", path, "chown", &owners)
+	}
+
+	/// Set modes of `modes` of `path` at once
+	pub fn set_mode(&self, path: &Path, modes: &[(PathBuf, u32)]) -> Result<()> {
+		let modes: Vec<(String, &PathBuf)> = modes
+			.iter()
+			.map(|(path, mode)| (format!("{mode:o}"), path))
+			.collect();
+		self.for_each_pair("set mode", path, "chmod", &modes)
+	}
+
+	/// Run `command -- value path` as root for each of `pairs` in as few shells
+	/// as possible, which fails if any of them fails
+	fn for_each_pair(
+		&self,
+		operation: &str,
+		path: &Path,
+		command: &str,
+		pairs: &[(String, &PathBuf)],
+	) -> Result<()> {
+		if pairs.is_empty() {
+			return Ok(());
+		}
+		let script = r#"command=$1 status=0
+shift
+while [ $# -gt 0 ]; do
+	$command -- "$1" "$2" || status=1
+	shift 2
+done
+exit $status"#;
+
+		let mut flat: Vec<&OsStr> = Vec::new();
+		for (value, path) in pairs {
+			flat.push(value.as_ref());
+			flat.push(path.as_ref());
+		}
+		for batch in batches(&flat, 2) {
+			let mut args: Vec<&OsStr> = vec![command.as_ref()];
+			args.extend(batch);
+			self.script(operation, path, script, &args)?;
+		}
+		Ok(())
+	}
+}
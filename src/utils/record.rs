@@ -0,0 +1,194 @@
+use super::reporter::{Hook, Progress, Reporter};
+use crate::error;
+use serde::Serialize;
+use std::{
+	path::{Path, PathBuf},
+	sync::{Mutex, PoisonError},
+};
+
+/// Kind of an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+	/// an app is processed
+	App,
+	/// a file or directory is copied
+	Copy,
+	/// an old file or directory is removed by `--clean`
+	Remove,
+	/// a hook is run
+	Hook,
+}
+
+/// Outcome of an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+	Ok,
+	Skipped,
+	Failed,
+}
+
+/// An operation performed by backup or setup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Operation {
+	pub kind: Kind,
+	/// app of the operation, `None` for global hooks
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub app: Option<String>,
+	/// hook stage and 0-based index, e.g., `pre_backup[0]`
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub hook: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub from: Option<PathBuf>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub to: Option<PathBuf>,
+	pub outcome: Outcome,
+	/// reason of skipping or failure
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub message: Option<String>,
+}
+
+impl Operation {
+	#[must_use]
+	pub fn new(kind: Kind, app: Option<&str>) -> Self {
+		Self {
+			kind,
+			app: app.map(ToString::to_string),
+			hook: None,
+			from: None,
+			to: None,
+			outcome: Outcome::Ok,
+			message: None,
+		}
+	}
+
+	/// Set the outcome by `ret`
+	#[must_use]
+	pub fn result<T>(mut self, ret: &error::Result<T>) -> Self {
+		if let Err(e) = ret {
+			self.outcome = Outcome::Failed;
+			self.message = Some(e.report());
+		}
+		self
+	}
+
+	/// Mark the operation skipped because of `reason`
+	#[must_use]
+	pub fn skipped(mut self, reason: &str) -> Self {
+		self.outcome = Outcome::Skipped;
+		self.message = Some(reason.to_string());
+		self
+	}
+}
+
+/// Reporter recording events as operations, and passing them on to another
+/// reporter.
+pub struct Recorder<'a> {
+	inner: &'a dyn Reporter,
+	operations: Mutex<Vec<Operation>>,
+}
+
+impl<'a> Recorder<'a> {
+	#[must_use]
+	pub fn new(inner: &'a dyn Reporter) -> Self {
+		Self {
+			inner,
+			operations: Mutex::new(Vec::new()),
+		}
+	}
+
+	fn record(&self, operation: Operation) {
+		self.operations
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.push(operation);
+	}
+
+	/// Return operations in the order they are recorded
+	#[must_use]
+	pub fn operations(&self) -> Vec<Operation> {
+		self.operations
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.clone()
+	}
+}
+
+impl Reporter for Recorder<'_> {
+	fn app_started(&self, action: &str, app: &str) {
+		self.inner.app_started(action, app);
+	}
+
+	fn app_skipped(&self, action: &str, app: &str, reason: &str) {
+		self.inner.app_skipped(action, app, reason);
+		self.record(Operation::new(Kind::App, Some(app)).skipped(reason));
+	}
+
+	fn app_finished(&self, action: &str, app: &str, result: &error::Result<()>) {
+		self.inner.app_finished(action, app, result);
+	}
+
+	fn file_removed(&self, app: &str, path: &Path, result: &error::Result<()>) {
+		self.inner.file_removed(app, path, result);
+		self.record(
+			Operation {
+				to: Some(path.to_path_buf()),
+				..Operation::new(Kind::Remove, Some(app))
+			}
+			.result(result),
+		);
+	}
+
+	fn file_copied(&self, app: &str, from: &Path, to: &Path, result: &error::Result<()>) {
+		self.inner.file_copied(app, from, to, result);
+		self.record(
+			Operation {
+				from: Some(from.to_path_buf()),
+				to: Some(to.to_path_buf()),
+				..Operation::new(Kind::Copy, Some(app))
+			}
+			.result(result),
+		);
+	}
+
+	fn progress(&self, app: &str, progress: Progress) {
+		self.inner.progress(app, progress);
+	}
+
+	fn file_ignored(&self, app: &str, path: &Path) {
+		self.inner.file_ignored(app, path);
+	}
+
+	fn file_skipped(&self, app: &str, path: &Path, reason: &str) {
+		self.inner.file_skipped(app, path, reason);
+		self.record(Operation {
+			from: Some(path.to_path_buf()),
+			..Operation::new(Kind::Copy, Some(app)).skipped(reason)
+		});
+	}
+
+	fn hook_started(&self, hook: &Hook) {
+		self.inner.hook_started(hook);
+	}
+
+	fn hook_finished(&self, hook: &Hook, result: &error::Result<()>) {
+		self.inner.hook_finished(hook, result);
+		self.record(
+			Operation {
+				hook: Some(format!("{}[{}]", hook.stage, hook.index)),
+				..Operation::new(Kind::Hook, hook.app)
+			}
+			.result(result),
+		);
+	}
+
+	fn warning(&self, app: Option<&str>, message: &str) {
+		self.inner.warning(app, message);
+	}
+
+	/// Operations are reported on stdout afterwards
+	fn hooks_to_stderr(&self) -> bool {
+		true
+	}
+}
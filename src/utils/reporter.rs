@@ -0,0 +1,174 @@
+//! Progress of backup and setup.
+//!
+//! `Config::backup_with` and `Config::setup_with` report what they do through
+//! a `Reporter`. With more than one job, apps are processed in parallel, so
+//! events of different apps may be interleaved.
+
+use crate::{colors::GREEN, error::Result, log};
+use std::path::Path;
+
+/// A hook being run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hook<'a> {
+	/// app of the hook, `None` for global hooks
+	pub app: Option<&'a str>,
+	/// e.g., `pre_backup`
+	pub stage: &'a str,
+	/// 0-based index of the hook in its stage
+	pub index: usize,
+	/// number of hooks in the stage
+	pub total: usize,
+	pub script: &'a str,
+}
+
+/// Files and bytes copied so far for an app
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+	pub files: u64,
+	pub bytes: u64,
+}
+
+impl Progress {
+	/// Count a copied file of `bytes`
+	pub fn add(&mut self, bytes: u64) {
+		self.files += 1;
+		self.bytes += bytes;
+	}
+}
+
+/// Return `bytes` in a human-readable unit, e.g., `1.5 MiB`
+#[must_use]
+pub fn human_bytes(bytes: u64) -> String {
+	const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+	#[allow(clippy::cast_precision_loss)]
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+	if unit == 0 {
+		format!("{bytes} B")
+	} else {
+		format!("{size:.1} {}", UNITS[unit])
+	}
+}
+
+/// Receiver of backup and setup events, all methods do nothing by default.
+///
+/// `action` is `backup` or `setup`.
+#[allow(unused_variables)]
+pub trait Reporter: Sync {
+	/// Backup or setup of `app` is started
+	fn app_started(&self, action: &str, app: &str) {}
+
+	/// `app` is skipped because of `reason`
+	fn app_skipped(&self, action: &str, app: &str, reason: &str) {}
+
+	/// `app` is done with its hooks, successfully or not
+	fn app_finished(&self, action: &str, app: &str, result: &Result<()>) {}
+
+	/// `path` is removed before copying because of `--clean`
+	fn file_removed(&self, app: &str, path: &Path, result: &Result<()>) {}
+
+	/// A file or directory is copied from `from` to `to`
+	fn file_copied(&self, app: &str, from: &Path, to: &Path, result: &Result<()>) {}
+
+	/// A file is copied while copying files of `app`, called for every file
+	/// even in directories
+	fn progress(&self, app: &str, progress: Progress) {}
+
+	/// `path` in a directory is not copied because it matches `ignore`
+	fn file_ignored(&self, app: &str, path: &Path) {}
+
+	/// `path` is not copied because of `reason`
+	fn file_skipped(&self, app: &str, path: &Path, reason: &str) {}
+
+	fn hook_started(&self, hook: &Hook) {}
+
+	fn hook_finished(&self, hook: &Hook, result: &Result<()>) {}
+
+	/// Something may be wrong, but backup or setup goes on
+	fn warning(&self, app: Option<&str>, message: &str) {}
+
+	/// Whether hooks write their stdout to stderr, to keep stdout for output
+	/// printed afterwards, e.g., a JSON report
+	fn hooks_to_stderr(&self) -> bool {
+		false
+	}
+}
+
+/// Reporter writing human-readable logs, the default one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LogReporter {
+	/// show a status line of progress if stderr is a terminal
+	pub progress: bool,
+}
+
+impl LogReporter {
+	/// Create a reporter showing progress
+	#[must_use]
+	pub fn new() -> Self {
+		Self { progress: true }
+	}
+}
+
+impl Reporter for LogReporter {
+	fn app_started(&self, action: &str, app: &str) {
+		info!("Starting {action} for {}", log::paint(GREEN, app));
+	}
+
+	fn app_skipped(&self, action: &str, app: &str, reason: &str) {
+		info!("Skip {action} for {}: {reason}", log::paint(GREEN, app));
+	}
+
+	fn file_removed(&self, _app: &str, path: &Path, result: &Result<()>) {
+		if result.is_ok() {
+			log!("clean: remove {}", path.display());
+		}
+	}
+
+	fn file_copied(&self, _app: &str, from: &Path, to: &Path, result: &Result<()>) {
+		if result.is_ok() {
+			log::print(
+				log::Level::Normal,
+				&format!("  {} -> {}\n", from.display(), to.display()),
+			);
+		}
+	}
+
+	fn progress(&self, app: &str, progress: Progress) {
+		if self.progress {
+			log::status(&format!(
+				"  {app}: {} files, {} copied",
+				progress.files,
+				human_bytes(progress.bytes)
+			));
+		}
+	}
+
+	fn file_ignored(&self, _app: &str, path: &Path) {
+		log!("ignore {}", path.display());
+	}
+
+	fn file_skipped(&self, app: &str, path: &Path, reason: &str) {
+		self.warning(Some(app), &format!("skip: {reason}: {}", path.display()));
+	}
+
+	fn hook_started(&self, hook: &Hook) {
+		let stage = hook.stage.replace('_', "-");
+		match hook.app {
+			Some(app) => info!(
+				"Running {stage} hooks for {} [{}/{}]",
+				log::paint(GREEN, app),
+				hook.index + 1,
+				hook.total
+			),
+			None => info!("Running {stage} hooks [{}/{}]", hook.index + 1, hook.total),
+		}
+	}
+
+	fn warning(&self, _app: Option<&str>, message: &str) {
+		warn!("{message}");
+	}
+}
@@ -0,0 +1,44 @@
+/// Return the edit distance between `a` and `b`.
+#[must_use]
+pub fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut row: Vec<usize> = (0..=b.len()).collect();
+
+	for (i, ca) in a.chars().enumerate() {
+		let mut prev = row[0];
+		row[0] = i + 1;
+		for (j, cb) in b.iter().enumerate() {
+			let next = if ca == *cb {
+				prev
+			} else {
+				1 + prev.min(row[j]).min(row[j + 1])
+			};
+			prev = row[j + 1];
+			row[j + 1] = next;
+		}
+	}
+
+	row[b.len()]
+}
+
+/// Return the candidate most similar to `name`, if it is similar enough.
+#[must_use]
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+	let max_distance = (name.chars().count() / 3).max(1);
+
+	candidates
+		.into_iter()
+		.map(|candidate| (edit_distance(name, candidate), candidate))
+		.filter(|(distance, _)| *distance <= max_distance)
+		.min_by_key(|(distance, _)| *distance)
+		.map(|(_, candidate)| candidate)
+}
+
+/// Return " (did you mean `...`?)" if there is a suggestion, otherwise an empty
+/// string.
+#[must_use]
+pub fn did_you_mean<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+	suggest(name, candidates)
+		.map(|s| format!(" (did you mean {s:?}?)"))
+		.unwrap_or_default()
+}
@@ -0,0 +1,242 @@
+//! Summary of a backup or setup run, printed after it finishes.
+
+use super::reporter::{Hook, Progress, Reporter, human_bytes};
+use crate::{colors::YELLOW, error::Result, log};
+use std::{
+	fmt::Write,
+	path::Path,
+	sync::{Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+/// Result of an app in a summary
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Status {
+	/// not finished yet, e.g., a dependency failed
+	#[default]
+	Running,
+	Ok,
+	Skipped,
+	Failed,
+}
+
+impl Status {
+	fn as_str(self) -> &'static str {
+		match self {
+			Self::Running => "-",
+			Self::Ok => "ok",
+			Self::Skipped => "skipped",
+			Self::Failed => "failed",
+		}
+	}
+}
+
+/// Statistics of an app
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppSummary {
+	pub name: String,
+	pub status: Status,
+	/// files copied, counting every file in directories
+	pub copied: u64,
+	/// files or directories not found
+	pub skipped: u64,
+	/// files in directories ignored by `ignore`
+	pub ignored: u64,
+	pub bytes: u64,
+	pub hooks: u64,
+	/// time from the first event of the app to its end
+	pub duration: Duration,
+	pub warnings: Vec<String>,
+}
+
+/// Reporter collecting statistics of each app, and passing events on to
+/// another reporter.
+pub struct Summary<'a> {
+	inner: &'a dyn Reporter,
+	apps: Mutex<Vec<(AppSummary, Instant)>>,
+	/// warnings not about an app, e.g., about the privilege helper
+	warnings: Mutex<Vec<String>>,
+}
+
+impl<'a> Summary<'a> {
+	#[must_use]
+	pub fn new(inner: &'a dyn Reporter) -> Self {
+		Self {
+			inner,
+			apps: Mutex::new(Vec::new()),
+			warnings: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Update the statistics of `app` with `f`
+	fn update(&self, app: &str, f: impl FnOnce(&mut AppSummary)) {
+		let mut apps = self.apps.lock().unwrap_or_else(PoisonError::into_inner);
+		let i = if let Some(i) = apps.iter().position(|(summary, _)| summary.name == app) {
+			i
+		} else {
+			let summary = AppSummary {
+				name: app.to_string(),
+				..Default::default()
+			};
+			apps.push((summary, Instant::now()));
+			apps.len() - 1
+		};
+		let (summary, start) = &mut apps[i];
+		f(summary);
+		summary.duration = start.elapsed();
+	}
+
+	/// Return statistics of apps in the order they are started
+	#[must_use]
+	pub fn apps(&self) -> Vec<AppSummary> {
+		let apps = self.apps.lock().unwrap_or_else(PoisonError::into_inner);
+		apps.iter().map(|(summary, _)| summary.clone()).collect()
+	}
+
+	/// Return warnings not about an app
+	#[must_use]
+	pub fn warnings(&self) -> Vec<String> {
+		self.warnings
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.clone()
+	}
+
+	/// Return the summary as a table followed by warnings of apps and general
+	/// warnings, empty if no app is run and nothing is warned
+	#[must_use]
+	pub fn table(&self) -> String {
+		let apps = self.apps();
+		let width = apps
+			.iter()
+			.map(|app| app.name.len())
+			.chain([3])
+			.max()
+			.unwrap_or_default();
+		let mut ret = String::new();
+		if !apps.is_empty() {
+			let _ = writeln!(
+				ret,
+				"  {:<width$}  {:>6}  {:>7}  {:>7}  {:>10}  {:>5}  {:>7}  status",
+				"app", "copied", "skipped", "ignored", "size", "hooks", "time"
+			);
+		}
+		for app in &apps {
+			let _ = writeln!(
+				ret,
+				"  {:<width$}  {:>6}  {:>7}  {:>7}  {:>10}  {:>5}  {:>6.2}s  {}",
+				app.name,
+				app.copied,
+				app.skipped,
+				app.ignored,
+				human_bytes(app.bytes),
+				app.hooks,
+				app.duration.as_secs_f64(),
+				app.status.as_str(),
+			);
+		}
+		for app in &apps {
+			for warning in &app.warnings {
+				let _ = writeln!(ret, "  {} {}: {warning}", log::paint(YELLOW, "!"), app.name);
+			}
+		}
+		for warning in self.warnings() {
+			let _ = writeln!(ret, "  {} {warning}", log::paint(YELLOW, "!"));
+		}
+
+		ret
+	}
+
+	/// Print the summary of `action` if any app is run
+	pub fn print(&self, action: &str) {
+		let table = self.table();
+		if !table.is_empty() {
+			info!("Summary of {action}");
+			log::print(log::Level::Normal, &table);
+		}
+	}
+}
+
+impl Reporter for Summary<'_> {
+	fn app_started(&self, action: &str, app: &str) {
+		self.inner.app_started(action, app);
+		self.update(app, |_| ());
+	}
+
+	fn app_skipped(&self, action: &str, app: &str, reason: &str) {
+		self.inner.app_skipped(action, app, reason);
+		self.update(app, |summary| summary.status = Status::Skipped);
+	}
+
+	fn app_finished(&self, action: &str, app: &str, result: &Result<()>) {
+		self.inner.app_finished(action, app, result);
+		self.update(app, |summary| {
+			summary.status = match (result, summary.status) {
+				(Err(_), _) => Status::Failed,
+				(Ok(()), Status::Skipped) => Status::Skipped,
+				(Ok(()), _) => Status::Ok,
+			};
+		});
+	}
+
+	fn file_removed(&self, app: &str, path: &Path, result: &Result<()>) {
+		self.inner.file_removed(app, path, result);
+	}
+
+	fn file_copied(&self, app: &str, from: &Path, to: &Path, result: &Result<()>) {
+		self.inner.file_copied(app, from, to, result);
+	}
+
+	fn progress(&self, app: &str, progress: Progress) {
+		self.inner.progress(app, progress);
+		self.update(app, |summary| {
+			summary.copied = progress.files;
+			summary.bytes = progress.bytes;
+		});
+	}
+
+	fn file_ignored(&self, app: &str, path: &Path) {
+		self.inner.file_ignored(app, path);
+		self.update(app, |summary| summary.ignored += 1);
+	}
+
+	fn file_skipped(&self, app: &str, path: &Path, reason: &str) {
+		self.inner.file_skipped(app, path, reason);
+		self.update(app, |summary| {
+			summary.skipped += 1;
+			summary
+				.warnings
+				.push(format!("{reason}: {}", path.display()));
+		});
+	}
+
+	fn hook_started(&self, hook: &Hook) {
+		self.inner.hook_started(hook);
+		if let Some(app) = hook.app {
+			self.update(app, |_| ());
+		}
+	}
+
+	fn hook_finished(&self, hook: &Hook, result: &Result<()>) {
+		self.inner.hook_finished(hook, result);
+		if let Some(app) = hook.app {
+			self.update(app, |summary| summary.hooks += 1);
+		}
+	}
+
+	fn warning(&self, app: Option<&str>, message: &str) {
+		self.inner.warning(app, message);
+		if let Some(app) = app {
+			self.update(app, |summary| summary.warnings.push(message.to_string()));
+		} else {
+			self.warnings
+				.lock()
+				.unwrap_or_else(PoisonError::into_inner)
+				.push(message.to_string());
+		}
+	}
+
+	fn hooks_to_stderr(&self) -> bool {
+		self.inner.hooks_to_stderr()
+	}
+}
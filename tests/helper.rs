@@ -1,3 +1,5 @@
+#![allow(clippy::missing_panics_doc)]
+
 use std::{env, fs, path::Path};
 
 /// Make an empty directory "test"
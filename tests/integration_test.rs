@@ -1,9 +1,25 @@
 mod helper;
 
-use dotbackup::{cli::Config, error};
+use dotbackup::{
+	Cli,
+	cli::{
+		Action, App, ChangeKind, Config, FileEntry, Format, Metadatas, Missing, Mode, State,
+		args::{OPTIONS, Scope},
+		completions::{self, Shell},
+		init::scan_dotfiles,
+		recipes,
+	},
+	error, field_names, find_program,
+	log::{self, Level},
+	privileged,
+	record::{Kind, Operation, Outcome, Recorder},
+	reporter::{Hook, LogReporter, Progress, Reporter, human_bytes},
+	summary::{Status, Summary},
+	warn,
+};
 use helper::*;
 use serial_test::serial;
-use std::{env, fs, path::Path};
+use std::{env, fs, path::Path, process, sync::Mutex};
 
 #[test]
 fn test_empty() {
@@ -292,10 +308,1574 @@ fn test_fail_exit() {
 
 	cleanup();
 	write_file("test/.config/app.txt", "app");
+	let e = config.backup().unwrap_err();
+	assert_eq!(e.r#type, error::Type::Hook);
+	assert_eq!(e.app.as_deref(), Some("app"));
+	assert_eq!(e.operation.as_deref(), Some("pre_backup[0]"));
+	assert_eq!(
+		e.to_string(),
+		"hook error in app: pre_backup[0]: sh returned non-zero: 1"
+	);
+	assert!(!Path::new("test/backup/.config/app.txt").is_file());
+
+	let status = process::Command::new(env!("CARGO_BIN_EXE_dotbackup"))
+		.args(["-f", "tests/configs/fail_exit.yml"])
+		.output()
+		.unwrap()
+		.status;
+	assert_eq!(status.code(), Some(5));
+}
+
+#[test]
+fn test_error_source() {
+	let e = Config::from_file(Path::new("tests/configs/missing.yml")).unwrap_err();
+	assert_eq!(e.r#type, error::Type::System);
+	assert_eq!(
+		e.path.as_deref(),
+		Some(Path::new("tests/configs/missing.yml"))
+	);
+	let source = std::error::Error::source(&e).unwrap();
+	assert_eq!(
+		source.downcast_ref::<std::io::Error>().unwrap().kind(),
+		std::io::ErrorKind::NotFound
+	);
+	// the source is shown once by error-chain reporters
+	assert!(!e.to_string().contains(&source.to_string()));
+	assert_eq!(e.report(), format!("{e}: {source}"));
+
+	let e = error::Error::combine(vec![
+		error::Error::new(error::Type::App, "a".to_string())
+			.with_app("x")
+			.with_path("p"),
+		dotbackup::sys_error!("b").with_app("x"),
+	])
+	.unwrap_err();
+	assert_eq!(e.r#type, error::Type::System);
+	assert_eq!(e.app.as_deref(), Some("x"));
+	assert_eq!(e.path, None);
+	assert_eq!(
+		e.msg,
+		"2 problems found\n  - application error in x: a (p)\n  - system error in x: b"
+	);
+
+	let status = process::Command::new(env!("CARGO_BIN_EXE_dotsetup"))
+		.args(["-f", "tests/configs/missing.yml"])
+		.output()
+		.unwrap()
+		.status;
+	assert_eq!(status.code(), Some(error::Type::System.exit_code().into()));
+	let status = process::Command::new(env!("CARGO_BIN_EXE_dotsetup"))
+		.arg("--foo")
+		.output()
+		.unwrap()
+		.status;
+	assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+#[serial]
+fn test_jobs() {
+	let mut config = Config::try_from(include_str!("configs/jobs.yml")).unwrap();
+	assert_eq!(config.get_jobs(), 4);
+	config.selected_apps = ["app_a", "app_b", "app_c", "app_d"]
+		.map(String::from)
+		.to_vec();
+
+	cleanup();
+	write_file("test/.config/app_a.txt", "a");
+	write_file("test/.config/app_b.txt", "b");
+	write_file("test/.config/app_c/c1.txt", "c1");
+	write_file("test/.config/app_c/c2.txt", "c2");
+	write_file("test/.config/app_d.txt", "d");
+	config.backup().unwrap();
+	assert_eq!(
+		fs::read_to_string("test/backup/.config/app_a.txt").unwrap(),
+		"a"
+	);
+	assert_eq!(
+		fs::read_to_string("test/backup/.config/app_b.txt").unwrap(),
+		"b"
+	);
+	assert_eq!(
+		fs::read_to_string("test/backup/.config/app_c/c1.txt").unwrap(),
+		"c1"
+	);
+	assert_eq!(
+		fs::read_to_string("test/backup/.config/app_c/c2.txt").unwrap(),
+		"c2"
+	);
+	assert_eq!(
+		fs::read_to_string("test/backup/.config/app_d.txt").unwrap(),
+		"d"
+	);
+	assert!(Path::new("test/backup/post_backup").is_file());
+
+	fs::remove_dir_all("test/.config").unwrap();
+	config.setup().unwrap();
+	assert_eq!(fs::read_to_string("test/.config/app_a.txt").unwrap(), "a");
+	assert_eq!(
+		fs::read_to_string("test/.config/app_c/c2.txt").unwrap(),
+		"c2"
+	);
+	assert_eq!(fs::read_to_string("test/.config/app_d.txt").unwrap(), "d");
+
+	// a failed app stops the run before global post hooks
+	cleanup();
+	config.selected_apps.clear();
 	assert!(
 		config
 			.backup()
-			.is_err_and(|e| e.r#type == error::Type::System)
+			.is_err_and(|e| e.r#type == error::Type::Hook)
 	);
-	assert!(!Path::new("test/backup/.config/app.txt").is_file());
+	assert!(!Path::new("test/backup/post_backup").is_file());
+	assert!(!Path::new("test/backup/.config/app_e.txt").is_file());
+}
+
+#[test]
+#[serial]
+fn test_depends_on() {
+	let mut config = Config::try_from(include_str!("configs/depends_on.yml")).unwrap();
+	assert_eq!(
+		config.get_selected_apps().unwrap(),
+		["m_tmux", "z_plugins", "a_nvim"]
+	);
+
+	config.selected_apps = vec!["z_plugins".to_string()];
+	assert_eq!(config.get_selected_apps().unwrap(), ["m_tmux", "z_plugins"]);
+
+	config.selected_apps.clear();
+	config.excluded_apps = vec!["a_nvim".to_string(), "z_plugins".to_string()];
+	assert_eq!(config.get_selected_apps().unwrap(), ["m_tmux"]);
+
+	config.excluded_apps = vec!["m_tmux".to_string()];
+	assert!(config.get_selected_apps().is_err_and(|e| {
+		e.r#type == error::Type::Argument
+			&& e.to_string()
+				.ends_with("app m_tmux is excluded, but app z_plugins depends on it")
+	}));
+
+	config.excluded_apps.clear();
+	config.selected_apps = vec!["a_nvim".to_string()];
+	cleanup();
+	config.setup().unwrap();
+	assert_eq!(
+		fs::read_to_string("test/order").unwrap(),
+		"m_tmux\nz_plugins\na_nvim\n"
+	);
+
+	config.jobs = 4;
+	cleanup();
+	config.setup().unwrap();
+	assert_eq!(
+		fs::read_to_string("test/order").unwrap(),
+		"m_tmux\nz_plugins\na_nvim\n"
+	);
+
+	let config = Config::try_from(
+		"
+backup_dir: test/backup
+apps:
+  a:
+    depends_on: [b]
+  b:
+    depends_on: [c]
+  c:
+    depends_on: [b]
+",
+	);
+	assert!(config.is_err_and(|e| {
+		e.r#type == error::Type::Config && e.to_string().ends_with("cycle among apps: b -> c -> b")
+	}));
+
+	let config = Config::try_from(
+		"
+backup_dir: test/backup
+apps:
+  a:
+    depends_on: [unknown]
+",
+	);
+	assert!(config.is_err_and(|e| e.r#type == error::Type::Config));
+}
+
+#[test]
+fn test_tags() {
+	let mut config = Config::try_from(include_str!("configs/tags.yml")).unwrap();
+
+	config.selected_apps = vec!["@shell".to_string(), "nvim".to_string()];
+	assert_eq!(config.get_selected_apps().unwrap(), ["bash", "nvim", "zsh"]);
+
+	config.selected_apps = vec!["@editor".to_string()];
+	config.excluded_apps = vec!["@gui".to_string()];
+	assert_eq!(config.get_selected_apps().unwrap(), ["nvim"]);
+
+	config.selected_apps.clear();
+	config.excluded_apps = vec!["@gui".to_string(), "zsh".to_string()];
+	assert_eq!(config.get_selected_apps().unwrap(), ["bash", "nvim"]);
+
+	config.excluded_apps = vec!["@unknown".to_string()];
+	assert!(
+		config
+			.get_selected_apps()
+			.is_err_and(|e| e.r#type == error::Type::Argument)
+	);
+}
+
+#[test]
+fn test_exclude() {
+	let args = |args: &[&str]| {
+		let args = ["-f", "tests/configs/tags.yml"].iter().chain(args);
+		Cli::default().parse_from(args.map(ToString::to_string))
+	};
+
+	let cli = args(&["-x", "firefox", "--exclude", "vscode"]).unwrap();
+	assert_eq!(
+		cli.config.get_selected_apps().unwrap(),
+		["bash", "nvim", "zsh"]
+	);
+
+	let cli = args(&["@editor", "!vscode"]).unwrap();
+	assert_eq!(cli.config.get_selected_apps().unwrap(), ["nvim"]);
+
+	let cli = args(&["!@shell", "!@gui"]).unwrap();
+	assert_eq!(cli.config.get_selected_apps().unwrap(), ["nvim"]);
+
+	let cli = args(&["-x", "unknown"]).unwrap();
+	assert!(
+		cli.config
+			.get_selected_apps()
+			.is_err_and(|e| e.r#type == error::Type::Argument)
+	);
+	assert!(args(&["-x"]).is_err_and(|e| e.r#type == error::Type::Argument));
+}
+
+#[test]
+#[serial]
+fn test_preflight() {
+	let mut config = Config::try_from(include_str!("configs/preflight.yml")).unwrap();
+
+	cleanup();
+	write_file("test/file", "not a directory");
+	config.selected_apps = vec!["app_b".to_string(), "ap_a".to_string()];
+	let msg = config.backup().unwrap_err().msg;
+	assert!(msg.starts_with("4 problems found"));
+	assert!(msg.contains("app not found: ap_a (did you mean \"app_a\"?)"));
+	assert!(msg.contains("invalid glob pattern in ignore"));
+	assert!(msg.contains("outside/app_b.txt"));
+	assert!(msg.contains("test/file is not a directory"));
+	assert!(!Path::new("test/pre_backup").exists());
+
+	config.selected_apps = vec!["app_a".to_string()];
+	let msg = config.setup().unwrap_err().msg;
+	assert!(msg.starts_with("2 problems found"));
+	assert!(msg.contains("invalid glob pattern in apps.app_a.ignore"));
+
+	config.ignore.clear();
+	config.apps.get_mut("app_a").unwrap().ignore.clear();
+	assert_eq!(config.preflight(&Action::Backup).unwrap(), ["app_a"]);
+
+	// even root cannot create files in /proc, although it is not read-only
+	if cfg!(target_os = "linux") {
+		config.backup_dir = "/proc/dotbackup".into();
+		let msg = config.preflight(&Action::Backup).unwrap_err().msg;
+		assert!(msg.contains("/proc/dotbackup is not writable"));
+	}
+}
+
+#[test]
+fn test_check() {
+	let diagnostics = Config::check(include_str!("configs/check.yml"), Format::Yaml).unwrap();
+	let diagnostics: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+	assert_eq!(
+		diagnostics,
+		[
+			"2:1: backup_dir: backup directory test/.config/backup is inside apps.app_b.files_linux[0]",
+			"3:1: unknown_option: unknown key",
+			"6:5: apps.app_a.file: unknown key (did you mean \"files\"?)",
+			"9:9: apps.app_a.files[1]: test/.config/app_a/a.txt is inside apps.app_a.files[0]",
+			"10:14: apps.app_a.ignore[0]: invalid glob pattern: Pattern syntax error near position 0: invalid range pattern",
+			"12:13: apps.app_b.files[0]: test/.config/app_a is also claimed by app app_a (apps.app_a.files[0])",
+			"12:13: apps.app_b.files[0]: test/.config/app_a contains apps.app_a.files[1]",
+			"12:33: apps.app_b.files[1]: outside/b.txt is outside of the dotfile root (test)",
+			"13:19: apps.app_b.files_linux[0]: test/.config contains apps.app_a.files[0]",
+			"13:19: apps.app_b.files_linux[0]: test/.config contains apps.app_a.files[1]",
+			"13:19: apps.app_b.files_linux[0]: test/.config contains apps.app_b.files[0]",
+			"14:3: apps.app_c: app has no files and no hooks",
+			"18:9: apps.app_d.files[0].requried: unknown key (did you mean \"required\"?)",
+			"19:9: apps.app_d.files[0].dest: dest is expected to be a relative path in the backup directory",
+			"20:18: apps.app_d.files[0].ignore[0]: invalid glob pattern: Pattern syntax error near position 0: invalid range pattern",
+			"22:5: apps.app_e.recipe: unknown recipe: nvm (did you mean \"nvim\"?)",
+			"23:5: apps.app_e.depends_on: dependency cycle among apps: app_e -> app_f -> app_e",
+			"23:25: apps.app_e.depends_on[1]: unknown app app_x (did you mean \"app_a\"?)",
+		]
+	);
+
+	// the command reports every problem, not only the first one which stops
+	// loading the configuration
+	let output = process::Command::new(env!("CARGO_BIN_EXE_dotbackup"))
+		.args(["-f", "tests/configs/check.yml", "check"])
+		.output()
+		.unwrap();
+	assert_eq!(output.status.code(), Some(3));
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains("check.yml:6:5: apps.app_a.file: unknown key"));
+	assert!(stdout.contains("check.yml:23:5: apps.app_e.depends_on: dependency cycle"));
+
+	let diagnostics = Config::check(include_str!("configs/basic.yml"), Format::Yaml).unwrap();
+	assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn test_strict() {
+	let e = Config::parse(include_str!("configs/unknown_option.yml"), true).unwrap_err();
+	assert_eq!(e.r#type, error::Type::Config);
+	assert!(e.msg.contains("2:1: unknown_option: unknown key"));
+	assert!(e.msg.contains("3:1: unknown_list: unknown key"));
+
+	let e = Config::try_from(include_str!("configs/strict.yml")).unwrap_err();
+	assert_eq!(
+		e.msg,
+		"5:5: apps.app.file: unknown key (did you mean \"files\"?)"
+	);
+
+	assert!(Config::parse(include_str!("configs/basic.yml"), true).is_ok());
+}
+
+#[test]
+fn test_json_schema() {
+	let schema: serde_json::Value = serde_json::from_str(&Config::json_schema()).unwrap();
+	let keys = |value: &serde_json::Value| {
+		let mut keys: Vec<String> = value["properties"]
+			.as_object()
+			.unwrap()
+			.keys()
+			.cloned()
+			.collect();
+		keys.sort();
+		keys
+	};
+	let fields = |fields: &[&str]| {
+		let mut fields: Vec<String> = fields.iter().map(ToString::to_string).collect();
+		fields.sort();
+		fields
+	};
+
+	assert_eq!(keys(&schema), fields(field_names::<Config>()));
+	assert_eq!(keys(&schema["$defs"]["App"]), fields(field_names::<App>()));
+	assert_eq!(schema["required"], serde_json::json!(["backup_dir"]));
+
+	// `src` is an alias of `path`
+	let file = &schema["$defs"]["FileOptions"];
+	assert_eq!(file["properties"]["src"], file["properties"]["path"]);
+	assert_eq!(
+		file["oneOf"],
+		serde_json::json!([{ "required": ["path"] }, { "required": ["src"] }])
+	);
+	// YAML gives `mode: 600` as an integer to validators
+	assert_eq!(
+		schema["$defs"]["Mode"]["type"],
+		serde_json::json!(["string", "integer"])
+	);
+}
+
+#[test]
+fn test_formats() {
+	let yaml = Config::from_file(Path::new("tests/configs/formats.yml")).unwrap();
+	let toml = Config::from_file(Path::new("tests/configs/formats.toml")).unwrap();
+	let json = Config::from_file(Path::new("tests/configs/formats.json")).unwrap();
+	assert_eq!(yaml, toml);
+	assert_eq!(yaml, json);
+
+	for format in Format::ALL {
+		let dump = yaml.dump(format).unwrap();
+		assert_eq!(Config::parse_as(&dump, format, true).unwrap(), yaml);
+	}
+
+	assert_eq!(
+		Cli::find_config(Path::new("tests/configs"), "formats"),
+		Path::new("tests/configs/formats.yml")
+	);
+	assert_eq!(
+		Cli::find_config(Path::new("tests/configs"), "unknown_option"),
+		Path::new("tests/configs/unknown_option.yml")
+	);
+	assert_eq!(
+		Cli::find_config(Path::new("tests/configs"), "missing"),
+		Path::new("tests/configs/missing.yml")
+	);
+
+	let diagnostics = Config::check(r#"{"backup_dir": "test", "jobz": 2}"#, Format::Json).unwrap();
+	assert_eq!(
+		diagnostics[0].to_string(),
+		"1:24: jobz: unknown key (did you mean \"jobs\"?)"
+	);
+	let diagnostics = Config::check("backup_dir = \"test\"\njobz = 2", Format::Toml).unwrap();
+	assert_eq!(
+		diagnostics[0].to_string(),
+		"jobz: unknown key (did you mean \"jobs\"?)"
+	);
+}
+
+#[test]
+#[serial]
+#[cfg(not(target_os = "windows"))]
+fn test_init() {
+	let home = env::current_dir().unwrap().join("test");
+	set_home(&home);
+
+	cleanup();
+	write_file("test/.bashrc", "bashrc");
+	write_file("test/.config/nvim/init.lua", "init.lua");
+	write_file("test/.ssh/config", "ssh");
+	write_file("test/.ssh/id_ed25519", "secret");
+
+	let apps = scan_dotfiles(&home);
+	assert_eq!(apps.keys().collect::<Vec<_>>(), ["bash", "nvim", "ssh"]);
+	assert_eq!(apps["bash"].files, [FileEntry::from("~/.bashrc")]);
+	assert_eq!(apps["bash"].tags, ["shell"]);
+	assert_eq!(apps["ssh"].files, [FileEntry::from("~/.ssh/config")]);
+	assert_eq!(apps["nvim"].ignore, recipes()["nvim"].ignore);
+
+	let args = [
+		"--init",
+		"--yes",
+		"-f",
+		"test/.config/dotbackup/dotbackup.yml",
+	];
+	let cli = Cli::default()
+		.parse_from(args.map(ToString::to_string))
+		.unwrap();
+	cli.run().unwrap();
+	let config = Config::from_file(Path::new("test/.config/dotbackup/dotbackup.yml")).unwrap();
+	assert_eq!(config.backup_dir, Path::new("~/dotfiles"));
+	assert_eq!(
+		config.apps["nvim"].files,
+		[FileEntry::from("~/.config/nvim")]
+	);
+	assert_eq!(
+		config.apps["dotbackup"].files,
+		[FileEntry::from("~/.config/dotbackup/dotbackup.yml")]
+	);
+
+	assert!(cli.run().is_err_and(|e| e.r#type == error::Type::Argument));
+
+	// end of input is not a yes
+	let status = process::Command::new(env!("CARGO_BIN_EXE_dotbackup"))
+		.args(["--init", "-f", "test/closed.yml"])
+		.stdin(process::Stdio::null())
+		.status()
+		.unwrap();
+	assert!(status.success());
+	let config = Config::from_file(Path::new("test/closed.yml")).unwrap();
+	assert!(config.apps.is_empty());
+}
+
+#[test]
+#[serial]
+fn test_edit() {
+	let home = env::current_dir().unwrap().join("test");
+	set_home(&home);
+
+	cleanup();
+	fs::copy("tests/configs/edit.yml", "test/dotbackup.yml").unwrap();
+	write_file("test/.bashrc", "bashrc");
+	write_file("test/.bash_profile", "bash_profile");
+	write_file("test/.gvimrc", "gvimrc");
+	write_file("test/.config/git/config", "git");
+
+	let run = |args: &[&str]| {
+		let args = ["-f", "test/dotbackup.yml"].iter().chain(args);
+		Cli::default()
+			.parse_from(args.map(ToString::to_string))
+			.and_then(|cli| cli.run())
+	};
+	let read = || fs::read_to_string("test/dotbackup.yml").unwrap();
+
+	run(&["add", "bash", "test/.bash_profile"]).unwrap();
+	run(&["add", "vim", "test/.gvimrc"]).unwrap();
+	run(&["add", "--backup", "git", "test/.config/git"]).unwrap();
+	assert_eq!(
+		read(),
+		"\
+# dotfiles of the test home
+backup_dir: ~/backup
+
+apps:
+  # shell
+  bash:
+    files:
+      - ~/.bashrc # main
+      - ~/.bash_profile
+    tags: [shell]
+
+  vim:
+    files: [~/.vimrc, ~/.gvimrc]
+  git:
+    files:
+      - ~/.config/git
+
+# end
+"
+	);
+	assert_eq!(
+		fs::read_to_string("test/backup/.config/git/config").unwrap(),
+		"git"
+	);
+	assert!(!Path::new("test/backup/.bashrc").exists());
+
+	// already added
+	run(&["add", "bash", "test/.bashrc"]).unwrap();
+	assert!(run(&["add", "bash", "/"]).is_err_and(|e| e.r#type == error::Type::Argument));
+	assert!(run(&["remove", "nvim"]).is_err_and(|e| e.r#type == error::Type::Argument));
+	assert!(
+		run(&["remove", "bash", "test/.zshrc"]).is_err_and(|e| e.r#type == error::Type::Argument)
+	);
+
+	run(&["remove", "bash", "test/.bashrc"]).unwrap();
+	run(&["remove", "vim", "test/.vimrc"]).unwrap();
+	run(&["remove", "git"]).unwrap();
+	assert_eq!(
+		read(),
+		"\
+# dotfiles of the test home
+backup_dir: ~/backup
+
+apps:
+  # shell
+  bash:
+    files:
+      - ~/.bash_profile
+    tags: [shell]
+
+  vim:
+    files: [~/.gvimrc]
+
+# end
+"
+	);
+
+	// not a command for dotsetup
+	let cli = Cli::dotsetup()
+		.parse_from(["-f", "test/dotbackup.yml", "add", "bash"].map(ToString::to_string))
+		.unwrap();
+	assert_eq!(cli.config.selected_apps, ["add", "bash"]);
+}
+
+#[test]
+fn test_recipe() {
+	for (name, recipe) in recipes() {
+		let files = [
+			&recipe.files,
+			&recipe.files_linux,
+			&recipe.files_macos,
+			&recipe.files_windows,
+		];
+		assert!(files.iter().any(|files| !files.is_empty()), "{name}");
+		for file in files.into_iter().flatten() {
+			assert!(
+				file.path().starts_with("~"),
+				"{name}: {}",
+				file.path().display()
+			);
+		}
+	}
+
+	let config = Config::try_from(include_str!("configs/recipe.yml")).unwrap();
+	let nvim = &config.apps["nvim"];
+	assert_eq!(nvim.files_linux, [FileEntry::from("~/.config/nvim")]);
+	assert_eq!(
+		nvim.files_windows,
+		[FileEntry::from("~/AppData/Local/nvim")]
+	);
+	assert_eq!(nvim.ignore, ["lazy-lock.json", ".luarc.json", "spell"]);
+	assert_eq!(nvim.tags, ["editor"]);
+
+	// files of the user take priority
+	let vim = &config.apps["vim"];
+	assert_eq!(vim.files, [FileEntry::from("~/.vimrc")]);
+	assert_eq!(vim.tags, ["editor", "minimal"]);
+	assert_eq!(config.match_apps("@editor").unwrap(), ["nvim", "vim"]);
+
+	let config = Config::try_from("backup_dir: ~/backup\napps: {vi: {recipe: vi}}");
+	assert!(config.is_err_and(|e| e.r#type == error::Type::Config && e.msg.contains("\"vim\"")));
+}
+
+#[test]
+#[serial]
+fn test_add_recipe() {
+	let home = env::current_dir().unwrap().join("test");
+	set_home(&home);
+
+	cleanup();
+	fs::copy("tests/configs/edit.yml", "test/dotbackup.yml").unwrap();
+	write_file("test/.wezterm.lua", "wezterm");
+
+	let run = |args: &[&str]| {
+		let args = ["-f", "test/dotbackup.yml"].iter().chain(args);
+		Cli::default()
+			.parse_from(args.map(ToString::to_string))
+			.and_then(|cli| cli.run())
+	};
+
+	run(&["add", "--recipe", "wezterm", "--backup"]).unwrap();
+	run(&["add", "--recipe", "vim", "vim"]).unwrap();
+	assert!(run(&["add", "--recipe", "foo"]).is_err_and(|e| e.r#type == error::Type::Argument));
+
+	let source = fs::read_to_string("test/dotbackup.yml").unwrap();
+	assert!(source.contains("\n  vim:\n    recipe: vim\n    files: [~/.vimrc]\n"));
+	assert!(source.contains("\n  wezterm:\n    recipe: wezterm\n\n# end\n"));
+	assert_eq!(
+		fs::read_to_string("test/backup/.wezterm.lua").unwrap(),
+		"wezterm"
+	);
+}
+
+#[test]
+fn test_commands() {
+	let parse = |cli: Cli, args: &[&str]| cli.parse_from(args.iter().map(ToString::to_string));
+	let basic = "tests/configs/basic.yml";
+
+	let cli = parse(Cli::default(), &["-f", basic, "list"]).unwrap();
+	assert!(matches!(cli.action, Action::List));
+	let cli = parse(Cli::default(), &["-f", basic, "--list"]).unwrap();
+	assert!(matches!(cli.action, Action::List));
+
+	let cli = parse(
+		Cli::default(),
+		&["-vj2", "-ftests/configs/basic.yml", "backup", "a", "!b"],
+	)
+	.unwrap();
+	assert!(matches!(cli.action, Action::Backup));
+	assert!(cli.config.verbose);
+	assert_eq!(cli.config.jobs, 2);
+	assert_eq!(cli.config.selected_apps, ["a"]);
+	assert_eq!(cli.config.excluded_apps, ["b"]);
+
+	let cli = parse(
+		Cli::default(),
+		&[
+			"--file=tests/configs/basic.yml",
+			"--jobs=3",
+			"setup",
+			"--",
+			"-a",
+			"list",
+		],
+	)
+	.unwrap();
+	assert!(matches!(cli.action, Action::Setup));
+	assert_eq!(cli.config.jobs, 3);
+	assert_eq!(cli.config.selected_apps, ["-a", "list"]);
+
+	// not a command after `--`, or for dotsetup
+	let cli = parse(Cli::default(), &["-f", basic, "--", "list"]).unwrap();
+	assert!(matches!(cli.action, Action::Backup));
+	assert_eq!(cli.config.selected_apps, ["list"]);
+	let cli = parse(Cli::dotsetup(), &["-f", basic, "list"]).unwrap();
+	assert!(matches!(cli.action, Action::Setup));
+	assert_eq!(cli.config.selected_apps, ["list"]);
+
+	let cli = parse(Cli::default(), &["-f", basic, "status", "-x", "b"]).unwrap();
+	assert!(matches!(cli.action, Action::Status));
+	assert_eq!(cli.config.excluded_apps, ["b"]);
+	let cli = parse(
+		Cli::default(),
+		&["-f", basic, "diff", "--setup", "--clean", "a"],
+	)
+	.unwrap();
+	assert!(matches!(cli.action, Action::Diff));
+	assert!(cli.setup && cli.config.clean);
+	assert_eq!(cli.config.selected_apps, ["a"]);
+
+	let cli = parse(Cli::default(), &["help", "add"]).unwrap();
+	assert!(matches!(cli.action, Action::Help));
+	assert_eq!(cli.command.unwrap().name, "add");
+	let cli = parse(Cli::default(), &["add", "--help"]).unwrap();
+	assert!(matches!(cli.action, Action::Help));
+	assert_eq!(cli.command.unwrap().name, "add");
+
+	for args in [
+		&["-f", basic, "list", "--clean"][..],
+		&["-f", basic, "check", "extra"],
+		&["-f", basic, "backup", "--list"],
+		&["-f", basic, "status", "--setup"],
+		&["--verbose=yes"],
+		&["--jobz", "2"],
+		&["-j"],
+		&["help", "halp"],
+	] {
+		let cli = parse(Cli::default(), args);
+		assert!(
+			cli.is_err_and(|e| e.r#type == error::Type::Argument),
+			"{args:?}"
+		);
+	}
+}
+
+#[test]
+fn test_completions() {
+	for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+		let script = completions::script(shell);
+		for opt in OPTIONS.iter().filter(|opt| opt.scope != Scope::Hidden) {
+			assert!(script.contains(opt.long), "{shell:?}: {}", opt.long);
+		}
+		assert!(script.contains("--complete-apps"));
+		assert!(script.contains("--complete-configs"));
+	}
+	assert!(
+		"ksh"
+			.parse::<Shell>()
+			.is_err_and(|e| e.r#type == error::Type::Argument)
+	);
+
+	let Some(bash) = find_program("bash") else {
+		return;
+	};
+	let bin = Path::new(env!("CARGO_BIN_EXE_dotbackup"));
+	let path = env::join_paths([bin.parent().unwrap()]).unwrap();
+	let complete = |words: &str| {
+		let script = format!(
+			"{}\nCOMP_WORDS=({words}); COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1))\n_dotbackup\necho \"${{COMPREPLY[*]}}\"",
+			completions::script(Shell::Bash)
+		);
+		let output = process::Command::new(&bash)
+			.args(["-c", &script])
+			.env("PATH", &path)
+			.output()
+			.unwrap();
+		String::from_utf8(output.stdout).unwrap().trim().to_string()
+	};
+
+	assert_eq!(
+		complete("dotsetup -f tests/configs/tags.yml ''"),
+		"bash firefox nvim vscode zsh @editor @gui @shell"
+	);
+	assert_eq!(
+		complete("dotbackup --file=tests/configs/tags.yml -x @"),
+		"@editor @gui @shell"
+	);
+	assert_eq!(complete("dotbackup re"), "remove");
+	assert_eq!(complete("dotbackup --form"), "--format");
+	assert_eq!(complete("dotbackup --format j"), "json");
+}
+
+#[test]
+#[serial]
+fn test_output() {
+	let mut config = Config::try_from(include_str!("configs/jobs.yml")).unwrap();
+	config.selected_apps = vec!["app_a".to_string(), "app_c".to_string()];
+
+	cleanup();
+	write_file("test/.config/app_a.txt", "a");
+	let summary = |operations: &[Operation]| {
+		operations
+			.iter()
+			.map(|op| (op.kind, op.app.clone(), op.hook.clone(), op.outcome))
+			.collect::<Vec<_>>()
+	};
+	let hook = |app: Option<&str>, hook: &str| {
+		(
+			Kind::Hook,
+			app.map(String::from),
+			Some(hook.to_string()),
+			Outcome::Ok,
+		)
+	};
+
+	let reporter = LogReporter::default();
+	let recorder = Recorder::new(&reporter);
+	config.backup_with(&recorder).unwrap();
+	// apps run concurrently, so only operations of each app are in order
+	let mut operations = recorder.operations();
+	operations.sort_by_key(|op| op.app.clone());
+	assert_eq!(
+		summary(&operations),
+		[
+			hook(None, "post_backup[0]"),
+			(Kind::Copy, Some("app_a".to_string()), None, Outcome::Ok),
+			hook(Some("app_a"), "post_backup[0]"),
+			(
+				Kind::Copy,
+				Some("app_c".to_string()),
+				None,
+				Outcome::Skipped
+			),
+			hook(Some("app_c"), "post_backup[0]"),
+		]
+	);
+	assert_eq!(
+		operations[1].to.as_deref(),
+		Some(Path::new("test/backup/.config/app_a.txt"))
+	);
+	assert_eq!(operations[3].message.as_deref(), Some("file not found"));
+
+	config.selected_apps = vec!["app_e".to_string()];
+	let recorder = Recorder::new(&reporter);
+	assert!(config.backup_with(&recorder).is_err());
+	let operations = recorder.operations();
+	assert_eq!(operations.len(), 1);
+	assert_eq!(operations[0].outcome, Outcome::Failed);
+
+	let run = |args: &[&str]| {
+		let output = process::Command::new(env!("CARGO_BIN_EXE_dotbackup"))
+			.args(["-f", "tests/configs/jobs.yml"])
+			.args(args)
+			.args(["--output", "json"])
+			.output()
+			.unwrap();
+		let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+		(output.status.success(), json)
+	};
+
+	let (success, json) = run(&["list"]);
+	assert!(success);
+	assert_eq!(json[0]["name"], "app_a");
+	assert_eq!(json[0]["files"][0], "test/.config/app_a.txt");
+	assert_eq!(json[0]["backup_dir"], "test/backup");
+	assert_eq!(json[0]["hooks"], 1);
+
+	let (success, json) = run(&["backup", "app_a"]);
+	assert!(success);
+	assert_eq!(json["action"], "backup");
+	assert_eq!(json["success"], true);
+	assert_eq!(json["operations"][0]["kind"], "copy");
+	assert_eq!(json["operations"][0]["outcome"], "ok");
+
+	let (success, json) = run(&["backup", "app_e"]);
+	assert!(!success);
+	assert_eq!(json["success"], false);
+	assert_eq!(json["operations"][0]["hook"], "pre_backup[0]");
+	assert_eq!(json["operations"][0]["outcome"], "failed");
+}
+
+#[test]
+#[serial]
+fn test_status() {
+	let mut config = Config::try_from(include_str!("configs/status.yml")).unwrap();
+
+	cleanup();
+	for (path, text) in [
+		("test/.config/app_a/same.txt", "same"),
+		("test/.config/app_a/changed.txt", "new"),
+		("test/.config/app_a/new.txt", "new"),
+		("test/.config/app_a/a.log", "new"),
+		("test/backup/.config/app_a/same.txt", "same"),
+		("test/backup/.config/app_a/changed.txt", "old"),
+		("test/backup/.config/app_a/old.txt", "old"),
+		("test/backup/.config/app_a/b.log", "old"),
+	] {
+		write_file(path, text);
+	}
+
+	let status = config.status().unwrap();
+	assert_eq!(status[0].name, "app_a");
+	assert_eq!(
+		status[0]
+			.files
+			.iter()
+			.map(|file| (file.path.to_str().unwrap(), file.state))
+			.collect::<Vec<_>>(),
+		[
+			("test/.config/app_a/changed.txt", State::Modified),
+			("test/.config/app_a/new.txt", State::NotBackedUp),
+			("test/.config/app_a/old.txt", State::OnlyInBackup),
+			("test/.config/app_a/same.txt", State::Same),
+		]
+	);
+	assert_eq!(status[1].name, "app_b");
+	assert!(status[1].files.is_empty());
+
+	let changes = |config: &Config, action: Action| {
+		config.diff(&action).unwrap()[0]
+			.changes
+			.iter()
+			.map(|change| (change.kind, change.path.to_str().unwrap().to_string()))
+			.collect::<Vec<_>>()
+	};
+	let change = |kind, path: &str| (kind, path.to_string());
+	assert_eq!(
+		changes(&config, Action::Backup),
+		[
+			change(
+				ChangeKind::Overwrite,
+				"test/backup/.config/app_a/changed.txt"
+			),
+			change(ChangeKind::Create, "test/backup/.config/app_a/new.txt"),
+		]
+	);
+
+	// ignored files are removed by clean too
+	config.clean = true;
+	assert_eq!(
+		changes(&config, Action::Backup),
+		[
+			change(ChangeKind::Delete, "test/backup/.config/app_a/b.log"),
+			change(
+				ChangeKind::Overwrite,
+				"test/backup/.config/app_a/changed.txt"
+			),
+			change(ChangeKind::Create, "test/backup/.config/app_a/new.txt"),
+			change(ChangeKind::Delete, "test/backup/.config/app_a/old.txt"),
+		]
+	);
+	assert_eq!(
+		changes(&config, Action::Setup),
+		[
+			change(ChangeKind::Delete, "test/.config/app_a/a.log"),
+			change(ChangeKind::Overwrite, "test/.config/app_a/changed.txt"),
+			change(ChangeKind::Delete, "test/.config/app_a/new.txt"),
+			change(ChangeKind::Create, "test/.config/app_a/old.txt"),
+		]
+	);
+
+	config.backup().unwrap();
+	assert!(changes(&config, Action::Backup).is_empty());
+	assert!(config.status().unwrap()[0].differences().next().is_none());
+
+	write_file("test/.config/app_b.txt", "b");
+	let output = process::Command::new(env!("CARGO_BIN_EXE_dotbackup"))
+		.args(["-f", "tests/configs/status.yml", "status"])
+		.output()
+		.unwrap();
+	assert!(output.status.success());
+	assert_eq!(
+		String::from_utf8(output.stdout).unwrap(),
+		"app_a: up to date\napp_b: 1 file differs\n  not backed up: test/.config/app_b.txt\n"
+	);
+
+	let output = process::Command::new(env!("CARGO_BIN_EXE_dotbackup"))
+		.args(["-f", "tests/configs/status.yml", "diff", "--setup", "app_b"])
+		.output()
+		.unwrap();
+	assert!(output.status.success());
+	assert_eq!(
+		String::from_utf8(output.stdout).unwrap(),
+		"app_b: no change\n"
+	);
+
+	let json = |args: &[&str]| {
+		let output = process::Command::new(env!("CARGO_BIN_EXE_dotbackup"))
+			.args(["-f", "tests/configs/status.yml", "--output=json"])
+			.args(args)
+			.output()
+			.unwrap();
+		assert!(output.status.success());
+		serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+	};
+	let status = json(&["status"]);
+	assert_eq!(status[1]["name"], "app_b");
+	assert_eq!(status[1]["files"][0]["path"], "test/.config/app_b.txt");
+	assert_eq!(
+		status[1]["files"][0]["backup"],
+		"test/backup/.config/app_b.txt"
+	);
+	assert_eq!(status[1]["files"][0]["state"], "not_backed_up");
+	let diff = json(&["diff", "app_b"]);
+	assert_eq!(diff[0]["changes"][0]["kind"], "create");
+	assert_eq!(
+		diff[0]["changes"][0]["path"],
+		"test/backup/.config/app_b.txt"
+	);
+}
+
+#[test]
+#[serial]
+fn test_log() {
+	let ((), captured) = log::capture(|| {
+		error!("e");
+		warn!("w");
+		log::set_level(Level::Verbose);
+		dotbackup::log!("v");
+		log::set_level(Level::Normal);
+	});
+	let levels: Vec<Level> = captured.iter().map(|(level, _)| *level).collect();
+	assert_eq!(levels, [Level::Quiet, Level::Normal, Level::Verbose]);
+
+	cleanup();
+	write_file("test/.config/app_a.txt", "a");
+	let run = |args: &[&str]| {
+		let output = process::Command::new(env!("CARGO_BIN_EXE_dotbackup"))
+			.args(["-f", "tests/configs/jobs.yml"])
+			.args(args)
+			.args(["backup", "app_a"])
+			.output()
+			.unwrap();
+		assert!(output.status.success());
+		(
+			String::from_utf8(output.stdout).unwrap(),
+			String::from_utf8(output.stderr).unwrap(),
+		)
+	};
+
+	let (stdout, stderr) = run(&[]);
+	assert!(stdout.contains("app_a post_backup"));
+	assert!(stderr.contains("==> Starting backup for app_a"));
+	assert!(!stderr.contains('\x1b'));
+
+	let (stdout, stderr) = run(&["-q"]);
+	assert_eq!(stdout, "");
+	assert!(!stderr.contains("==>"));
+
+	let (_, stderr) = run(&["-vv"]);
+	assert!(stderr.contains("hook script:"));
+
+	// `verbose` in the configuration does not override `-q`
+	let config = fs::read_to_string("tests/configs/jobs.yml").unwrap();
+	write_file("test/verbose.yml", &format!("verbose: true\n{config}"));
+	let run_verbose = |args: &[&str]| {
+		let output = process::Command::new(env!("CARGO_BIN_EXE_dotbackup"))
+			.args(["-f", "test/verbose.yml", "--clean"])
+			.args(args)
+			.args(["backup", "app_a"])
+			.output()
+			.unwrap();
+		assert!(output.status.success());
+		String::from_utf8(output.stderr).unwrap()
+	};
+	assert!(run_verbose(&[]).contains("==> clean: remove"));
+	assert!(!run_verbose(&["-q"]).contains("==>"));
+
+	let (_, stderr) = run(&["-q", "--log-file", "test/log.txt"]);
+	assert!(!stderr.contains("==>"));
+	run(&["--log-file=test/log.txt"]);
+	let log = fs::read_to_string("test/log.txt").unwrap();
+	assert!(log.contains("==> Starting backup for app_a"));
+	assert!(log.contains("+ echo app_a post_backup\n"));
+	assert!(log.contains("\napp_a post_backup\n"));
+	assert_eq!(log.matches("Starting backup").count(), 1);
+
+	// hook output is captured line by line, stderr is not merged into stdout
+	let (ret, captured) =
+		log::capture(|| dotbackup::run_hook("echo out\necho err >&2", Path::new("test")));
+	ret.unwrap();
+	let lines: Vec<&str> = captured.iter().map(|(_, line)| line.as_str()).collect();
+	assert!(lines.contains(&"out\n"));
+	assert!(lines.contains(&"err\n"));
+	assert!(lines.contains(&"+ echo err\n"));
+}
+
+#[derive(Default)]
+struct Events(Mutex<Vec<String>>);
+
+impl Reporter for Events {
+	fn app_started(&self, action: &str, app: &str) {
+		self.0.lock().unwrap().push(format!("{action} {app}"));
+	}
+
+	fn file_copied(
+		&self,
+		app: &str,
+		_from: &Path,
+		to: &Path,
+		result: &dotbackup::error::Result<()>,
+	) {
+		assert!(result.is_ok());
+		self.0
+			.lock()
+			.unwrap()
+			.push(format!("copy {app} {}", to.display()));
+	}
+
+	fn progress(&self, app: &str, progress: Progress) {
+		self.0.lock().unwrap().push(format!(
+			"progress {app} {} {}",
+			progress.files, progress.bytes
+		));
+	}
+
+	fn file_skipped(&self, app: &str, path: &Path, reason: &str) {
+		self.0
+			.lock()
+			.unwrap()
+			.push(format!("skip {app} {}: {reason}", path.display()));
+	}
+
+	fn hook_finished(&self, hook: &Hook, result: &dotbackup::error::Result<()>) {
+		self.0.lock().unwrap().push(format!(
+			"hook {} {}[{}/{}] {}",
+			hook.app.unwrap_or("-"),
+			hook.stage,
+			hook.index,
+			hook.total,
+			result.is_ok()
+		));
+	}
+}
+
+#[test]
+#[serial]
+fn test_reporter() {
+	let mut config = Config::try_from(include_str!("configs/jobs.yml")).unwrap();
+	config.jobs = 1;
+	config.selected_apps = vec!["app_a".to_string(), "app_c".to_string()];
+
+	cleanup();
+	write_file("test/.config/app_a.txt", "a");
+	let events = Events::default();
+	let (ret, output) = log::capture(|| config.backup_with(&events));
+	ret.unwrap();
+	assert!(output.iter().all(|(_, text)| !text.contains("Starting")));
+	assert_eq!(
+		events.0.into_inner().unwrap(),
+		[
+			"backup app_a",
+			"progress app_a 1 1",
+			"copy app_a test/backup/.config/app_a.txt",
+			"hook app_a post_backup[0/1] true",
+			"backup app_c",
+			"skip app_c test/.config/app_c: file not found",
+			"hook app_c post_backup[0/1] true",
+			"hook - post_backup[0/1] true",
+		]
+	);
+
+	config.selected_apps = vec!["app_e".to_string()];
+	let events = Events::default();
+	assert!(config.setup_with(&events).is_ok());
+	assert!(config.backup_with(&events).is_err());
+	assert_eq!(
+		events.0.into_inner().unwrap(),
+		[
+			"setup app_e",
+			"skip app_e test/backup/.config/app_e.txt: file not found",
+			"hook app_e pre_backup[0/1] false",
+		]
+	);
+
+	// progress counts every file in directories
+	let mut config = Config::try_from(include_str!("configs/basic.yml")).unwrap();
+	config.selected_apps = vec!["app_a".to_string()];
+	cleanup();
+	write_file("test/.config/app_a/a1.txt", "a1");
+	write_file("test/.config/app_a/a2.txt", "a2");
+	write_file("test/.config/app_a/sub/a3.txt", "a3");
+	let events = Events::default();
+	config.backup_with(&events).unwrap();
+	let events = events.0.into_inner().unwrap();
+	assert_eq!(
+		events.iter().rfind(|e| e.starts_with("progress")).unwrap(),
+		"progress app_a 3 6"
+	);
+
+	assert_eq!(human_bytes(1000), "1000 B");
+	assert_eq!(human_bytes(1536), "1.5 KiB");
+	assert_eq!(human_bytes(3 << 30), "3.0 GiB");
+}
+
+#[test]
+#[serial]
+fn test_summary() {
+	let mut config = Config::try_from(include_str!("configs/ignore.yml")).unwrap();
+	config.apps.insert(
+		"missing".to_string(),
+		App {
+			files: vec!["test/.config/missing".into()],
+			..Default::default()
+		},
+	);
+	config.apps.insert("empty".to_string(), App::default());
+
+	cleanup();
+	write_file("test/.config/app/ignore/global_ignore", "global_ignore");
+	write_file("test/.config/app/ignore/app_ignore", "app_ignore");
+	write_file("test/.config/app/global_ignore", "global_ignore");
+	write_file("test/.config/app/app_ignore", "app_ignore");
+	let reporter = LogReporter::default();
+	let summary = Summary::new(&reporter);
+	config.backup_with(&summary).unwrap();
+
+	let apps = summary.apps();
+	let names: Vec<&str> = apps.iter().map(|app| app.name.as_str()).collect();
+	assert_eq!(names, ["app", "empty", "missing"]);
+	assert_eq!(apps[0].status, Status::Ok);
+	assert_eq!((apps[0].copied, apps[0].ignored, apps[0].bytes), (2, 4, 23));
+	assert_eq!(apps[1].status, Status::Skipped);
+	assert_eq!(apps[2].skipped, 1);
+	assert_eq!(apps[2].warnings, ["file not found: test/.config/missing"]);
+
+	let table = summary.table();
+	assert!(
+		table
+			.lines()
+			.next()
+			.unwrap()
+			.contains("copied  skipped  ignored")
+	);
+	assert!(table.contains("missing: file not found: test/.config/missing"));
+
+	// warnings not about an app, e.g., about doas, are kept too
+	let summary = Summary::new(&reporter);
+	assert_eq!(summary.table(), "");
+	summary.warning(None, "general");
+	assert_eq!(summary.warnings(), ["general"]);
+	assert!(summary.table().ends_with(" general\n"));
+
+	let (_, output) = log::capture(|| config.backup());
+	assert!(
+		output
+			.iter()
+			.any(|(_, text)| text.contains("Summary of backup"))
+	);
+}
+
+#[test]
+#[serial]
+fn test_missing_files() {
+	let config = Config::try_from(include_str!("configs/missing_files.yml")).unwrap();
+	assert_eq!(config.apps["warn"].missing, None);
+	assert_eq!(config.apps["ignore"].missing, Some(Missing::Ignore));
+	assert!(config.apps["required"].files[0].required());
+	assert!(!config.apps["required"].files[1].required());
+	let only = |names: &[&str], strict_missing| {
+		let mut config = config.clone();
+		config.apps.retain(|name, _| names.contains(&name.as_str()));
+		config.strict_missing = strict_missing;
+		config
+	};
+
+	cleanup();
+	write_file("test/.config/warn/present", "warn");
+	write_file("test/.config/required/present", "required");
+	let reporter = LogReporter::default();
+	let summary = Summary::new(&reporter);
+	only(&["warn", "ignore"], false)
+		.backup_with(&summary)
+		.unwrap();
+	let apps = summary.apps();
+	assert_eq!(apps[0].name, "ignore");
+	assert_eq!(
+		apps[1].warnings,
+		["file not found: test/.config/warn/absent"]
+	);
+	assert_eq!((apps[0].skipped, apps[0].warnings.len()), (0, 0));
+	assert!(Path::new("test/backup/.config/warn/present").is_file());
+
+	let e = only(&["error"], false).backup().unwrap_err();
+	assert_eq!(e.r#type, error::Type::App);
+	assert_eq!(e.app.as_deref(), Some("error"));
+	assert_eq!(
+		e.path.as_deref(),
+		Some(Path::new("test/.config/error/absent"))
+	);
+	assert_eq!(e.operation.as_deref(), Some("backup"));
+	assert_eq!(e.exit_code(), 6);
+
+	let e = only(&["required"], false).backup().unwrap_err();
+	assert_eq!(
+		e.path.as_deref(),
+		Some(Path::new("test/.config/required/absent"))
+	);
+
+	let e = only(&["warn"], true).backup().unwrap_err();
+	assert_eq!(
+		e.path.as_deref(),
+		Some(Path::new("test/.config/warn/absent"))
+	);
+
+	let status = |args: &[&str]| {
+		process::Command::new(env!("CARGO_BIN_EXE_dotbackup"))
+			.args(["-f", "tests/configs/missing_files.yml"])
+			.args(args)
+			.output()
+			.unwrap()
+			.status
+			.code()
+	};
+	assert_eq!(status(&["warn"]), Some(0));
+	assert_eq!(status(&["--strict-missing", "warn"]), Some(6));
+	assert_eq!(status(&["setup", "ignore"]), Some(0));
+	assert_eq!(status(&["setup", "--strict-missing", "ignore"]), Some(6));
+}
+
+#[test]
+#[serial]
+fn test_file_options() {
+	let config = Config::try_from(include_str!("configs/file_options.yml")).unwrap();
+	let files = &config.apps["app"].files;
+	assert_eq!(files[0].dest(), Some(Path::new("ssh/config")));
+	assert_eq!(files[0].mode(), Some(Mode(0o600)));
+	assert_eq!(files[1].ignore(), ["cache"]);
+	assert_eq!(files[2], FileEntry::from("test/.config/other"));
+
+	for (mode, expected) in [
+		("0644", Some(0o644)),
+		("600", Some(0o600)),
+		("0o644", Some(0o644)),
+		("0o755", Some(0o755)),
+		("'0o755'", Some(0o755)),
+		("800", None),
+		("0x1a4", None),
+	] {
+		let source = format!(
+			"backup_dir: b\napps:\n  a:\n    files:\n      - path: f\n        mode: {mode}"
+		);
+		let config = Config::try_from(source.as_str());
+		assert_eq!(
+			config
+				.ok()
+				.map(|config| config.apps["a"].files[0].mode().unwrap().0),
+			expected,
+			"{mode}"
+		);
+	}
+	// TOML and JSON numbers do not tell how they were written
+	let toml = "backup_dir = \"b\"\n[[apps.a.files]]\npath = \"f\"\nmode = ";
+	for mode in ["0o644", "420"] {
+		let error = Config::parse_as(&format!("{toml}{mode}"), Format::Toml, false)
+			.unwrap_err()
+			.to_string();
+		assert!(
+			error.contains("invalid mode 420, write it as a string"),
+			"{error}"
+		);
+	}
+	let toml_config = Config::parse_as(&format!("{toml}\"0o644\""), Format::Toml, false).unwrap();
+	assert_eq!(toml_config.apps["a"].files[0].mode(), Some(Mode(0o644)));
+	let json = r#"{"backup_dir": "b", "apps": {"a": {"files": [{"path": "f", "mode": 600}]}}}"#;
+	assert!(Config::parse_as(json, Format::Json, false).is_err());
+
+	// errors in file options are reported as they are
+	for (file, message) in [
+		("path: f\n        mode: '0999'", "invalid mode"),
+		("dest: f", "missing field `path`"),
+		("[f]", "expected a path or a map of file options"),
+	] {
+		let source = format!("backup_dir: b\napps:\n  a:\n    files:\n      - {file}");
+		let error = Config::try_from(source.as_str()).unwrap_err().to_string();
+		assert!(error.contains(message), "{error}");
+	}
+
+	let dumped = Format::Yaml.serialize(&config).unwrap();
+	assert!(dumped.contains("mode: '0600'"));
+	assert_eq!(Config::try_from(dumped.as_str()).unwrap(), config);
+
+	cleanup();
+	write_file("test/.ssh/config", "ssh");
+	write_file("test/.config/app/cache/file", "cache");
+	write_file("test/.config/app/keep", "keep");
+	write_file("test/.config/other/cache/file", "cache");
+	config.backup().unwrap();
+	assert!(Path::new("test/backup/ssh/config").is_file());
+	assert!(!Path::new("test/backup/.ssh").exists());
+	assert!(Path::new("test/backup/.config/app/keep").is_file());
+	assert!(!Path::new("test/backup/.config/app/cache").exists());
+	assert!(Path::new("test/backup/.config/other/cache/file").is_file());
+
+	fs::remove_dir_all("test/.ssh").unwrap();
+	config.setup().unwrap();
+	assert_eq!(fs::read_to_string("test/.ssh/config").unwrap(), "ssh");
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		for path in ["test/backup/ssh/config", "test/.ssh/config"] {
+			let mode = fs::metadata(path).unwrap().permissions().mode();
+			assert_eq!(mode & 0o7777, 0o600, "{path}");
+		}
+	}
+
+	let mut config = config;
+	if let FileEntry::Options(options) = &mut config.apps.get_mut("app").unwrap().files[0] {
+		options.dest = Some("../ssh".into());
+	}
+	let e = config.backup().unwrap_err();
+	assert_eq!(e.r#type, error::Type::Config);
+}
+
+#[test]
+#[serial]
+fn test_outside_dotfile_root() {
+	let config = Config::try_from(include_str!("configs/outside.yml")).unwrap();
+	assert_eq!(
+		config.apps["system"].files[0].path(),
+		Path::new("test/etc/hosts")
+	);
+	let diagnostics = Config::check(include_str!("configs/outside.yml"), Format::Yaml).unwrap();
+	assert!(diagnostics.is_empty(), "{diagnostics:?}");
+
+	cleanup();
+	write_file("test/etc/hosts", "hosts");
+	write_file("test/etc/nixos/configuration.nix", "nix");
+	write_file("test/home/.profile", "profile");
+	config.backup().unwrap();
+	assert!(Path::new("test/backup/system/hosts").is_file());
+	assert!(Path::new("test/backup/system/nixos/configuration.nix").is_file());
+	assert!(Path::new("test/backup/.profile").is_file());
+
+	fs::remove_dir_all("test/etc").unwrap();
+	config.setup().unwrap();
+	assert_eq!(fs::read_to_string("test/etc/hosts").unwrap(), "hosts");
+	assert!(Path::new("test/etc/nixos/configuration.nix").is_file());
+
+	let mut config = config;
+	config
+		.apps
+		.get_mut("system")
+		.unwrap()
+		.files
+		.push("test/etc/fstab".into());
+	let e = config.backup().unwrap_err();
+	assert_eq!(e.r#type, error::Type::Config);
+	assert!(e.msg.contains("or to have a dest"));
+}
+
+#[test]
+#[serial]
+#[cfg(unix)]
+fn test_privileged() {
+	use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+	let config = Config::try_from(include_str!("configs/privileged.yml")).unwrap();
+	assert!(config.apps["etc"].privileged);
+	assert!(!config.apps["profile"].privileged);
+
+	cleanup();
+	// a fake sudo logging its commands, running them as the current user
+	write_file(
+		"test/bin/sudo",
+		"#!/bin/sh\necho \"$*\" >> test/helper.log\n[ \"$1\" = -v ] || exec \"$@\"\n",
+	);
+	fs::set_permissions("test/bin/sudo", fs::Permissions::from_mode(0o755)).unwrap();
+	write_file("test/etc/pacman.conf", "pacman");
+	fs::set_permissions("test/etc/pacman.conf", fs::Permissions::from_mode(0o604)).unwrap();
+	// another owner can only be given by root
+	let metadata = fs::metadata("test/etc/pacman.conf").unwrap();
+	let user = (metadata.uid(), metadata.gid());
+	let owner = if user.0 == 0 {
+		std::os::unix::fs::chown("test/etc/pacman.conf", Some(1234), Some(5678)).unwrap();
+		(1234, 5678)
+	} else {
+		user
+	};
+	write_file("test/etc/sudoers.d/a", "a");
+	write_file("test/etc/sudoers.d/b", "b");
+	write_file("test/etc/sudoers.d/c.bak", "c");
+	write_file("test/home/.profile", "profile");
+
+	let reporter = LogReporter::default();
+	let summary = Summary::new(&reporter);
+	config.backup_with(&summary).unwrap();
+	let log = fs::read_to_string("test/helper.log").unwrap();
+	let lines: Vec<&str> = log.lines().collect();
+	assert_eq!(lines[0], "-v");
+	assert_eq!(lines.iter().filter(|line| **line == "-v").count(), 1);
+	// files are found and copied as root, without ignored files
+	// backups are owned by the owner of the backup directory
+	let backup_dir = fs::metadata("test/backup").unwrap();
+	assert!(log.contains(&format!(
+		"cp -P -p {}:{} test/backup/etc/pacman.conf f test/etc/pacman.conf ",
+		backup_dir.uid(),
+		backup_dir.gid()
+	)));
+	assert!(log.contains(" d test/etc/sudoers.d test/backup/etc/sudoers.d "));
+	assert!(log.contains(" f test/etc/sudoers.d/b test/backup/etc/sudoers.d/b"));
+	assert!(!log.contains("c.bak test/backup"));
+	assert!(!log.contains(".profile"));
+	assert_eq!(summary.apps()[0].copied, 3);
+	assert_eq!(summary.apps()[0].ignored, 1);
+	assert!(Path::new("test/backup/etc/sudoers.d/b").is_file());
+	assert!(!Path::new("test/backup/etc/sudoers.d/c.bak").exists());
+	let mode = |path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+	assert_eq!(mode("test/backup/etc/pacman.conf"), 0o640);
+	// modes and owners are read as root
+	let metadatas = Metadatas::load(&Metadatas::path(Path::new("test/backup"), "etc")).unwrap();
+	let metadata = metadatas.0[Path::new("etc/pacman.conf")];
+	assert_eq!(metadata.mode, Mode(0o604));
+	assert_eq!((metadata.uid, metadata.gid), (Some(owner.0), Some(owner.1)));
+	let metadata = metadatas.0[Path::new("etc/sudoers.d/a")];
+	assert_eq!((metadata.uid, metadata.gid), (Some(user.0), Some(user.1)));
+	assert!(!metadatas.0.contains_key(Path::new("etc/sudoers.d/c.bak")));
+
+	fs::remove_dir_all("test/etc").unwrap();
+	fs::remove_file("test/helper.log").unwrap();
+	write_file("test/backup/etc/sudoers.d/c.bak", "c");
+	config.setup().unwrap();
+	let log = fs::read_to_string("test/helper.log").unwrap();
+	assert!(log.contains("cp -P  test/etc/pacman.conf f test/backup/etc/pacman.conf "));
+	assert!(!Path::new("test/etc/sudoers.d/c.bak").exists());
+	// modes and owners of each file are changed at once
+	assert!(log.contains("chmod 640 test/etc/pacman.conf"));
+	assert!(log.lines().any(|line| {
+		line.contains("sh chmod ")
+			&& ["sudoers.d/a ", "sudoers.d/b ", "sudoers.d"]
+				.iter()
+				.all(|path| line.contains(&format!("test/etc/{path}")))
+	}));
+	assert_eq!(log.matches("sh -c ").count(), 6);
+	assert_eq!(fs::read_to_string("test/etc/sudoers.d/a").unwrap(), "a");
+	assert_eq!(mode("test/etc/pacman.conf"), 0o640);
+	let metadata = fs::metadata("test/etc/pacman.conf").unwrap();
+	assert_eq!((metadata.uid(), metadata.gid()), owner);
+
+	// doas asks for the password for every command without persist
+	assert!(!privileged::doas_persists(
+		"permit :wheel\n# permit persist :wheel\n"
+	));
+	assert!(privileged::doas_persists("permit persist :wheel"));
+	assert!(privileged::doas_persists("permit nopass keepenv root"));
+
+	let mut config = config;
+	config.privilege_helper = Some("test/bin/missing".to_string());
+	let e = config.backup().unwrap_err();
+	assert_eq!(e.r#type, error::Type::System);
+}
+
+#[test]
+#[serial]
+#[cfg(unix)]
+fn test_metadata() {
+	use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+	let config = Config::try_from(include_str!("configs/metadata.yml")).unwrap();
+	let chmod = |path, mode| fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+	let mode = |path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+	cleanup();
+	write_file("test/.ssh/config", "ssh");
+	write_file("test/.gnupg/private-keys", "private");
+	write_file("test/.gnupg/pubring", "public");
+	chmod("test/.ssh/config", 0o600);
+	chmod("test/.ssh", 0o700);
+	chmod("test/.gnupg/private-keys", 0o644);
+	chmod("test/.gnupg/pubring", 0o644);
+	config.backup().unwrap();
+
+	let metadatas = Metadatas::load(&Metadatas::path(Path::new("test/backup"), "secrets")).unwrap();
+	let config_metadata = metadatas.0[Path::new(".ssh/config")];
+	assert_eq!(config_metadata.mode, Mode(0o600));
+	assert_eq!(
+		config_metadata.uid,
+		Some(fs::metadata("test/.ssh/config").unwrap().uid())
+	);
+	assert_eq!(metadatas.0[Path::new(".ssh")].mode, Mode(0o700));
+
+	// e.g., checked out by git with the default umask
+	chmod("test/backup/.ssh/config", 0o644);
+	chmod("test/backup/.ssh", 0o755);
+	fs::remove_dir_all("test/.ssh").unwrap();
+	fs::remove_dir_all("test/.gnupg").unwrap();
+	config.setup().unwrap();
+	assert_eq!(mode("test/.ssh/config"), 0o600);
+	assert_eq!(mode("test/.ssh"), 0o700);
+	assert_eq!(mode("test/.gnupg/private-keys"), 0o600);
+	assert_eq!(mode("test/.gnupg/pubring"), 0o644);
+
+	// only modes which differ are changed
+	log::set_level(Level::Verbose);
+	let (ret, captured) = log::capture(|| config.setup());
+	log::set_level(Level::Normal);
+	ret.unwrap();
+	let chmods: Vec<&str> = captured
+		.iter()
+		.map(|(_, message)| message.as_str())
+		.filter(|message| message.contains("chmod"))
+		.collect();
+	assert_eq!(chmods.len(), 2, "{chmods:?}");
+	assert!(chmods[0].contains("chmod 0600 test/.ssh/config"));
+	assert!(chmods[1].contains("chmod 0600 test/.gnupg/private-keys"));
+
+	// backups without metadata are still restored
+	fs::remove_dir_all("test/backup/.dotbackup").unwrap();
+	fs::remove_dir_all("test/.ssh").unwrap();
+	config.setup().unwrap();
+	assert_eq!(mode("test/.ssh/config"), 0o644);
 }